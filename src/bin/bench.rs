@@ -0,0 +1,114 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: bench [options] [CONTENDER...]
+
+Reads the same amount of output from each contender through a pipe, like
+`cargo run --release --bin s13 | pv > /dev/null`, and prints the best throughput of the runs.
+The binaries have to be built first, with `cargo build --release`.
+
+contenders (default all):
+  s13         the last of the steps
+  template    firehose, filling a buffer and patching the numbers in place
+  writev      firehose --backend writev, gathering the lines without numbers
+  vmsplice    firehose --backend vmsplice
+  io_uring    firehose --backend io_uring
+  words       firehose --format words, spelling the numbers out
+
+options:
+  --gib N     GiB to read from each run (default 4)
+  --runs N    runs per contender (default 3)
+";
+
+const CONTENDERS: [(&str, &str, &[&str]); 6] = [
+    ("s13", "s13", &[]),
+    ("template", "firehose", &[]),
+    ("writev", "firehose", &["--backend", "writev"]),
+    ("vmsplice", "firehose", &["--backend", "vmsplice"]),
+    ("io_uring", "firehose", &["--backend", "io_uring"]),
+    ("words", "firehose", &["--format", "words"]),
+];
+
+const GIB: u64 = 1 << 30;
+
+/// Time to read `bytes` bytes of `bin`'s output.
+fn measure(bin: &Path, args: &[&str], bytes: u64) -> io::Result<Duration> {
+    let mut child = Command::new(bin)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let mut buf = vec![0; 1 << 20];
+    let mut left = bytes;
+    let start = Instant::now();
+    while left > 0 {
+        let len = std::cmp::min(buf.len() as u64, left) as usize;
+        let n = stdout.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} stopped short", bin.display()),
+            ));
+        }
+        left -= n as u64;
+    }
+    let elapsed = start.elapsed();
+    child.kill()?;
+    child.wait()?;
+    Ok(elapsed)
+}
+
+fn run(names: &[String], gib: u64, runs: u32) -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let dir = exe.parent().unwrap();
+    for &(name, bin, args) in &CONTENDERS {
+        if !names.is_empty() && !names.iter().any(|n| n == name) {
+            continue;
+        }
+        let mut best = Duration::MAX;
+        for _ in 0..runs {
+            best = std::cmp::min(best, measure(&dir.join(bin), args, gib * GIB)?);
+        }
+        let rate = gib as f64 / best.as_secs_f64();
+        println!("{name:<10} {rate:>6.2} GiB/s");
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut gib = 4;
+    let mut runs = 3;
+    let mut names = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let number = |value: Option<String>| value.and_then(|v| v.parse().ok()).filter(|&n| n > 0);
+        match arg.as_str() {
+            "--gib" => match number(args.next()) {
+                Some(n) => gib = n,
+                None => return usage(&format!("{arg} needs a positive number")),
+            },
+            "--runs" => match number(args.next()) {
+                Some(n) => runs = n as u32,
+                None => return usage(&format!("{arg} needs a positive number")),
+            },
+            name if CONTENDERS.iter().any(|&(n, _, _)| n == name) => names.push(arg),
+            other => return usage(&format!("unknown argument: {other}")),
+        }
+    }
+    match run(&names, gib, runs) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bench: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage(error: &str) -> ExitCode {
+    eprint!("bench: {error}\n\n{USAGE}");
+    ExitCode::from(2)
+}
@@ -0,0 +1,798 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Seek, Write};
+use std::num::NonZeroUsize;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use fizzbuzz_firehose::binary::{binary_buzz, decode};
+use fizzbuzz_firehose::buffer::BUF_SIZE;
+use fizzbuzz_firehose::compact::{compress, expand};
+use fizzbuzz_firehose::counter::{NumFormat, Pad};
+use fizzbuzz_firehose::direct::Direct;
+use fizzbuzz_firehose::gzip::GzEncoder;
+use fizzbuzz_firehose::layout::{Class, Layout};
+use fizzbuzz_firehose::memfd::{sealed_buzz, spawn_with_stdin};
+use fizzbuzz_firehose::mmap::mmap_buzz;
+use fizzbuzz_firehose::parallel::parallel_buzz;
+use fizzbuzz_firehose::pretty::{PrettyOptions, pretty_buzz};
+use fizzbuzz_firehose::pwrite::Pwrite;
+use fizzbuzz_firehose::roman::roman_buzz;
+use fizzbuzz_firehose::sys;
+use fizzbuzz_firehose::template::{Output, Stride, buzz_len, fast_buzz, fast_buzz_to};
+use fizzbuzz_firehose::uring::Uring;
+use fizzbuzz_firehose::vmsplice::Vmsplice;
+use fizzbuzz_firehose::words::word_buzz;
+use fizzbuzz_firehose::writev::gather_buzz;
+
+const USAGE: &str = "\
+usage: firehose [options]
+       firehose decode < records
+       firehose expand < fixture
+
+Writes FizzBuzz to stdout. `decode` turns the binary format back into plain text, `expand`
+writes out the stream described by a --compact fixture.
+
+options:
+  --format FORMAT     plain (default), jsonl, csv, tsv, words, roman, binary or pretty
+  --start N           first number, negative for the template formats (default 1)
+  --end N             last number (default u64::MAX)
+  --pad zeros|spaces  pad every line to a fixed width (plain only, not for negative
+                      numbers)
+  --group SEP         separate thousands with SEP, as in 1,234,567 (plain only)
+  --header            start with a header row (csv and tsv only)
+  --number-lines      prefix every line with its line number, like `nl` (plain, jsonl, csv
+                      and tsv only)
+  --only CLASSES      only write the numbers, fizz, buzz or fizzbuzz lines, comma separated
+                      or repeated (plain, jsonl, csv and tsv only). With --number-lines the
+                      lines keep their numbers from the full sequence.
+  --step K            only write every K-th line (plain, jsonl, csv and tsv only)
+  --offset N          skip N lines before the first one written with --step (default 0)
+  --countdown         count down from --end to --start (plain, jsonl, csv and tsv only)
+  --compact           write a fixture that `expand` turns into the output, rather than the
+                      output itself (plain, jsonl, csv and tsv only)
+  --gzip              compress the output with gzip
+  --backend BACKEND   how the bytes get to stdout: auto (default) to suit whatever it is,
+                      write, pwrite for aligned blocks to a regular file, or for plain, jsonl,
+                      csv and tsv only, writev to gather the lines without numbers from one
+                      shared copy, vmsplice to map them into a pipe without copying, or
+                      io_uring to queue the writes up
+  -o, --output PATH   write to PATH rather than stdout. The template formats are generated
+                      on every core, unless they're going through --compact or --gzip.
+  --threads N         threads for --output and --exec (default one per core)
+  --mmap              with --output, generate straight into a mapping of the file rather
+                      than writing it (template formats only, without --compact or --gzip)
+  --direct            with --output, write around the page cache with O_DIRECT, on one core
+  --exec CMD ARG...   generate into a sealed memfd rather than stdout, and run CMD with it as
+                      stdin, taking the rest of the arguments for it (template formats only,
+                      without --compact or --gzip)
+  -v, --verbose       report what the output is, and the backend and buffer sizes, on stderr
+  --columns           fill the terminal width with columns (pretty only)
+  --rate N            lines per second, or rows with --columns, 0 for unlimited (pretty
+                      only, default 20)
+";
+
+enum Command {
+    Generate,
+    Decode,
+    Expand,
+}
+
+#[derive(PartialEq, Eq)]
+enum Format {
+    Plain,
+    Jsonl,
+    Csv,
+    Tsv,
+    Words,
+    Roman,
+    Binary,
+    /// Colours when writing to a terminal, optionally in columns, and at a readable pace.
+    Pretty,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Write,
+    /// Aligned blocks, when stdout is a regular file.
+    Pwrite,
+    /// Zero-copy, when stdout is a pipe.
+    Vmsplice,
+    /// Queued writes from registered buffers, or plain writes where that's not allowed.
+    IoUring,
+    /// Gathered writes, leaving out the copy of the lines without numbers.
+    Writev,
+}
+
+const BACKENDS: [(&str, Backend); 5] = [
+    ("write", Backend::Write),
+    ("pwrite", Backend::Pwrite),
+    ("writev", Backend::Writev),
+    ("vmsplice", Backend::Vmsplice),
+    ("io_uring", Backend::IoUring),
+];
+
+/// What's behind stdout, going by `fstat(2)`.
+#[derive(Clone, Copy)]
+enum Target {
+    Pipe,
+    File { block_size: usize },
+    Terminal,
+    Socket,
+    Other,
+}
+
+impl Target {
+    fn of(fd: BorrowedFd) -> io::Result<Target> {
+        let metadata = File::from(fd.try_clone_to_owned()?).metadata()?;
+        let file_type = metadata.file_type();
+        Ok(if file_type.is_fifo() {
+            Target::Pipe
+        } else if file_type.is_file() {
+            Target::File {
+                block_size: metadata.blksize() as usize,
+            }
+        } else if file_type.is_socket() {
+            Target::Socket
+        } else if fd.is_terminal() {
+            Target::Terminal
+        } else {
+            Target::Other
+        })
+    }
+}
+
+const DEFAULT_RATE: u32 = 20;
+/// Buffers for a regular file, rounded up to whole blocks.
+const FILE_BUF_SIZE: usize = 1024 * 1024;
+/// Small enough that a terminal fills steadily, rather than in big jumps.
+const TERMINAL_BUF_SIZE: usize = 4096;
+/// The most we'll fill at a time, however much a pipe or socket holds. Any more and a buffer's
+/// out of cache by the time it's been filled.
+const MAX_BUF_SIZE: usize = 512 * 1024;
+/// What we ask for, before the kernel caps it at `net.core.wmem_max`.
+const SOCKET_SEND_BUFFER: usize = 4 * 1024 * 1024;
+
+struct Args {
+    command: Command,
+    format: Format,
+    /// Anywhere from `-u64::MAX` to `u64::MAX`.
+    start: i128,
+    end: i128,
+    fmt: NumFormat,
+    header: bool,
+    number_lines: bool,
+    /// Empty for every class.
+    only: Vec<Class>,
+    stride: Stride,
+    compact: bool,
+    gzip: bool,
+    /// `None` to pick one to suit stdout.
+    backend: Option<Backend>,
+    output: Option<PathBuf>,
+    threads: Option<usize>,
+    mmap: bool,
+    direct: bool,
+    /// The command and its arguments, empty if there's none.
+    exec: Vec<String>,
+    verbose: bool,
+    columns: bool,
+    rate: Option<u32>,
+}
+
+impl Args {
+    fn parse() -> Result<Args, String> {
+        let mut args = Args {
+            command: Command::Generate,
+            format: Format::Plain,
+            start: 1,
+            end: u64::MAX as i128,
+            fmt: NumFormat::default(),
+            header: false,
+            number_lines: false,
+            only: Vec::new(),
+            stride: Stride::ALL,
+            compact: false,
+            gzip: false,
+            backend: None,
+            output: None,
+            threads: None,
+            mmap: false,
+            direct: false,
+            exec: Vec::new(),
+            verbose: false,
+            columns: false,
+            rate: None,
+        };
+        let mut argv = std::env::args().skip(1).peekable();
+        if let Some(command) = argv.next_if(|arg| arg == "decode" || arg == "expand") {
+            args.command = match command.as_str() {
+                "decode" => Command::Decode,
+                _ => Command::Expand,
+            };
+            return match argv.next() {
+                Some(arg) => Err(format!("{command} doesn't take arguments: {arg}")),
+                None => Ok(args),
+            };
+        }
+        while let Some(arg) = argv.next() {
+            let mut value = || argv.next().ok_or(format!("{arg} expects a value"));
+            match arg.as_str() {
+                "--format" => {
+                    args.format = match value()?.as_str() {
+                        "plain" => Format::Plain,
+                        "jsonl" => Format::Jsonl,
+                        "csv" => Format::Csv,
+                        "tsv" => Format::Tsv,
+                        "words" => Format::Words,
+                        "roman" => Format::Roman,
+                        "binary" => Format::Binary,
+                        "pretty" => Format::Pretty,
+                        other => return Err(format!("unknown format: {other}")),
+                    }
+                }
+                "--start" => args.start = parse_signed(&value()?)?,
+                "--end" => args.end = parse_signed(&value()?)?,
+                "--pad" => {
+                    let pad = match value()?.as_str() {
+                        "zeros" => Pad::Zeros,
+                        "spaces" => Pad::Spaces,
+                        other => return Err(format!("unknown padding: {other}")),
+                    };
+                    args.fmt = NumFormat {
+                        sep: args.fmt.sep,
+                        ..NumFormat::padded(pad)
+                    };
+                }
+                "--group" => args.fmt.sep = Some(parse_sep(&value()?)?),
+                "--header" => args.header = true,
+                "--number-lines" => args.number_lines = true,
+                "--only" => {
+                    for class in value()?.split(',') {
+                        args.only.push(match class {
+                            "numbers" => Class::Number,
+                            "fizz" => Class::Fizz,
+                            "buzz" => Class::Buzz,
+                            "fizzbuzz" => Class::FizzBuzz,
+                            other => return Err(format!("unknown class of line: {other}")),
+                        });
+                    }
+                }
+                "--step" => {
+                    args.stride.step = match parse_num(&value()?)? {
+                        0 => return Err("--step must be at least 1".to_string()),
+                        step => step,
+                    }
+                }
+                "--offset" => args.stride.offset = parse_num(&value()?)?,
+                "--countdown" => args.stride.descending = true,
+                "--compact" => args.compact = true,
+                "--gzip" => args.gzip = true,
+                "--backend" => {
+                    args.backend = match value()?.as_str() {
+                        "auto" => None,
+                        name => match BACKENDS.iter().find(|&&(n, _)| n == name) {
+                            Some(&(_, backend)) => Some(backend),
+                            None => return Err(format!("unknown backend: {name}")),
+                        },
+                    }
+                }
+                "-o" | "--output" => args.output = Some(PathBuf::from(value()?)),
+                "--threads" => {
+                    args.threads = match parse_num(&value()?)? {
+                        0 => return Err("--threads must be at least 1".to_string()),
+                        threads => Some(threads as usize),
+                    }
+                }
+                "--mmap" => args.mmap = true,
+                "--direct" => args.direct = true,
+                "--exec" => {
+                    args.exec = argv.by_ref().collect();
+                    if args.exec.is_empty() {
+                        return Err(format!("{arg} expects a command"));
+                    }
+                }
+                "-v" | "--verbose" => args.verbose = true,
+                "--columns" => args.columns = true,
+                "--rate" => {
+                    let value = value()?;
+                    let rate = value
+                        .parse()
+                        .map_err(|e| format!("invalid rate {value:?}: {e}"))?;
+                    args.rate = Some(rate);
+                }
+                "-h" | "--help" => {
+                    print!("{USAGE}");
+                    std::process::exit(0);
+                }
+                other => return Err(format!("unknown argument: {other}")),
+            }
+        }
+        if args.format != Format::Plain && args.fmt != NumFormat::default() {
+            return Err("--pad and --group only apply to the plain format".to_string());
+        }
+        if args.header && !matches!(args.format, Format::Csv | Format::Tsv) {
+            return Err("--header only applies to the csv and tsv formats".to_string());
+        }
+        if (args.columns || args.rate.is_some()) && args.format != Format::Pretty {
+            return Err("--columns and --rate only apply to the pretty format".to_string());
+        }
+        let templated = args.compact
+            || args.number_lines
+            || !args.only.is_empty()
+            || args.stride != Stride::ALL;
+        if templated && args.template().is_none() {
+            return Err(
+                "--compact, --number-lines, --only, --step, --offset and --countdown only apply \
+                 to the template formats"
+                    .to_string(),
+            );
+        }
+        let negative = args.start < 0 || args.end < 0;
+        if negative && args.template().is_none() {
+            return Err("negative numbers only apply to the template formats".to_string());
+        }
+        // The sign makes negative numbers a byte wider than everything else
+        if negative && args.fmt.pad != Pad::None {
+            return Err("--pad doesn't support negative numbers".to_string());
+        }
+        let templated_backend = matches!(
+            args.backend,
+            Some(Backend::Writev | Backend::Vmsplice | Backend::IoUring)
+        );
+        if templated_backend && (args.template().is_none() || args.compact || args.gzip) {
+            return Err(
+                "--backend writev, vmsplice and io_uring only apply to the template formats, \
+                 without --compact or --gzip"
+                    .to_string(),
+            );
+        }
+        if args.output.is_some() && args.backend.is_some() {
+            return Err("--backend only applies to stdout, not --output".to_string());
+        }
+        if args.threads.is_some() && args.output.is_none() && args.exec.is_empty() {
+            return Err("--threads only applies to --output and --exec".to_string());
+        }
+        if args.mmap && args.output.is_none() {
+            return Err("--mmap only applies to --output".to_string());
+        }
+        if args.mmap && (args.template().is_none() || args.compact || args.gzip) {
+            return Err(
+                "--mmap only applies to the template formats, without --compact or --gzip"
+                    .to_string(),
+            );
+        }
+        if args.direct && args.output.is_none() {
+            return Err("--direct only applies to --output".to_string());
+        }
+        if args.direct && (args.mmap || args.threads.is_some()) {
+            return Err("--direct doesn't go with --mmap or --threads".to_string());
+        }
+        if !args.exec.is_empty() {
+            if args.output.is_some() || args.backend.is_some() {
+                return Err("--exec doesn't go with --output or --backend".to_string());
+            }
+            if args.bare_template().is_none() {
+                return Err(
+                    "--exec only applies to the template formats, without --compact or --gzip"
+                        .to_string(),
+                );
+            }
+        }
+        Ok(args)
+    }
+
+    fn pretty_options(&self) -> PrettyOptions {
+        let stdout = io::stdout();
+        let color = stdout.is_terminal() && !self.gzip;
+        let width = match self.columns {
+            true => sys::terminal_width(stdout.as_raw_fd())
+                .ok()
+                .filter(|&width| width > 0)
+                .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+                .or(Some(80)),
+            false => None,
+        };
+        let rate = match self.rate.unwrap_or(DEFAULT_RATE) {
+            0 => None,
+            rate => Some(rate),
+        };
+        PrettyOptions { color, width, rate }
+    }
+
+    /// Threads to generate on, one per core unless told otherwise.
+    fn threads(&self) -> usize {
+        self.threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, NonZeroUsize::get))
+    }
+
+    /// The layout and preamble, when the output is nothing but what the template engine makes of
+    /// them, and so exactly as long as [`buzz_len`] says.
+    fn bare_template(&self) -> Option<(Layout, Vec<u8>)> {
+        match self.command {
+            Command::Generate if !self.compact && !self.gzip => self.template(),
+            _ => None,
+        }
+    }
+
+    /// The layout and preamble, for the formats that go through the template engine.
+    fn template(&self) -> Option<(Layout, Vec<u8>)> {
+        let (layout, preamble) = match self.format {
+            Format::Plain => (Layout::plain(self.fmt), Vec::new()),
+            Format::Jsonl => (Layout::jsonl(), Vec::new()),
+            Format::Csv | Format::Tsv => {
+                let sep = if self.format == Format::Csv {
+                    b','
+                } else {
+                    b'\t'
+                };
+                let header = match self.header {
+                    true => Layout::delimited_header(sep),
+                    false => Vec::new(),
+                };
+                (Layout::delimited(sep), header)
+            }
+            Format::Words | Format::Roman | Format::Binary | Format::Pretty => return None,
+        };
+        let layout = match self.number_lines {
+            true => layout.numbered(),
+            false => layout,
+        };
+        match self.only.is_empty() {
+            true => Some((layout, preamble)),
+            false => Some((layout.only(&self.only), preamble)),
+        }
+    }
+}
+
+fn parse_num(s: &str) -> Result<u64, String> {
+    s.parse().map_err(|e| format!("invalid number {s:?}: {e}"))
+}
+
+fn parse_signed(s: &str) -> Result<i128, String> {
+    match s.parse::<i128>() {
+        Ok(n) if n.unsigned_abs() <= u64::MAX as u128 => Ok(n),
+        Ok(_) => Err(format!("number out of range: {s}")),
+        Err(e) => Err(format!("invalid number {s:?}: {e}")),
+    }
+}
+
+fn parse_sep(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        &[sep] if sep.is_ascii() && !sep.is_ascii_digit() && sep != b'\n' => Ok(sep),
+        _ => Err(format!(
+            "separator must be a single non-digit ASCII character: {s:?}"
+        )),
+    }
+}
+
+fn generate<W: Write>(args: &Args, capacity: usize, out: &mut W) -> io::Result<()> {
+    let range = args.start..=args.end;
+    // Only the template formats go negative
+    let unsigned = args.start as u64..=args.end as u64;
+    match args.command {
+        Command::Decode => decode(io::stdin().lock(), out),
+        Command::Expand => expand(io::stdin().lock(), out),
+        Command::Generate => match (args.template(), &args.format) {
+            (Some((layout, preamble)), _) if args.compact => {
+                compress(&layout, &preamble, range, args.stride, out)
+            }
+            (Some((layout, preamble)), _) => {
+                out.write_all(&preamble)?;
+                fast_buzz(&layout, range, args.stride, capacity, out)
+            }
+            (None, Format::Words) => word_buzz(unsigned, out),
+            (None, Format::Roman) => roman_buzz(unsigned, out),
+            (None, Format::Pretty) => pretty_buzz(unsigned, &args.pretty_options(), out),
+            (None, _) => binary_buzz(unsigned, out),
+        },
+    }
+}
+
+/// Straight from the template engine into stdout, bypassing `Stdout`.
+fn template_to(args: &Args, out: &mut impl Output) -> io::Result<()> {
+    let (layout, preamble) = args.template().unwrap();
+    out.send(&preamble)?;
+    fast_buzz_to(&layout, args.start..=args.end, args.stride, out)
+}
+
+/// Tunes whatever's behind stdout, and picks the buffer size to suit it.
+fn buffer_size(target: Target, fd: RawFd, verbose: bool) -> usize {
+    match target {
+        Target::Pipe => pipe_buffer_size(fd, verbose),
+        Target::File { block_size } => {
+            if verbose {
+                eprintln!("firehose: output is a regular file, with {block_size} byte blocks");
+            }
+            FILE_BUF_SIZE.next_multiple_of(block_size)
+        }
+        Target::Socket => {
+            let Ok(send_buffer) = sys::set_send_buffer(fd, SOCKET_SEND_BUFFER) else {
+                return BUF_SIZE;
+            };
+            if verbose {
+                eprintln!("firehose: output is a socket, with a {send_buffer} byte send buffer");
+            }
+            // Half of it's for data, and half of that for us to fill, as with a pipe
+            (send_buffer / 4).clamp(BUF_SIZE, MAX_BUF_SIZE)
+        }
+        Target::Terminal => {
+            if verbose {
+                eprintln!("firehose: output is a terminal");
+            }
+            TERMINAL_BUF_SIZE
+        }
+        Target::Other => BUF_SIZE,
+    }
+}
+
+/// Grows the pipe behind stdout as far as it'll go, and picks the buffer size to match: half the
+/// pipe, so there's room for the next buffer while the reader's busy with the last. A buffer the
+/// size of the whole pipe has us waiting on the reader to empty it every time.
+fn pipe_buffer_size(fd: RawFd, verbose: bool) -> usize {
+    let Ok(was) = sys::pipe_size(fd) else {
+        return BUF_SIZE;
+    };
+    let max = sys::pipe_max_size().unwrap_or(was);
+    let mut size = was;
+    let mut want = max;
+    // Past the per-user limit on pipe memory, only smaller pipes are allowed
+    while want > was {
+        match sys::set_pipe_size(fd, want) {
+            Ok(got) => {
+                size = got;
+                break;
+            }
+            Err(_) => want /= 2,
+        }
+    }
+    if verbose {
+        eprintln!("firehose: output is a pipe, grown from {was} to {size} bytes (max {max})");
+    }
+    (size / 2).clamp(BUF_SIZE, MAX_BUF_SIZE)
+}
+
+/// The backend that suits `target`, for the template formats or not.
+///
+/// Never vmsplice: a reader that passes the pages on with `splice(2)`, rather than reading them,
+/// could still see them change.
+fn auto_backend(target: Target) -> Backend {
+    match target {
+        Target::File { .. } => Backend::Pwrite,
+        _ => Backend::Write,
+    }
+}
+
+/// Through `out`, which buffers however it likes.
+fn write_to<W: Write>(args: &Args, capacity: usize, mut out: W) -> io::Result<()> {
+    if args.gzip {
+        let mut gz = GzEncoder::new(out)?;
+        generate(args, capacity, &mut gz)?;
+        gz.finish()?.flush()
+    } else {
+        generate(args, capacity, &mut out)?;
+        out.flush()
+    }
+}
+
+/// Refuses `len` bytes of output from `offset` on if they won't fit on the file system, rather than
+/// running out of room hours in, and has the file system set them aside where it can.
+fn reserve(file: BorrowedFd, offset: u64, len: u128, verbose: bool) -> io::Result<()> {
+    let free = sys::free_space(file.as_raw_fd())?;
+    if len > free as u128 {
+        return Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!("the output is {len} bytes, and there are only {free} bytes free for it"),
+        ));
+    }
+    // fallocate(2) won't take an empty range
+    if len == 0 {
+        return Ok(());
+    }
+    let preallocated = sys::preallocate(file.as_raw_fd(), offset, len as u64)?;
+    if verbose {
+        match preallocated {
+            true => eprintln!("firehose: preallocated {len} bytes, of {free} free"),
+            false => {
+                eprintln!("firehose: {len} bytes fit in {free} free, but can't be preallocated")
+            }
+        }
+    }
+    Ok(())
+}
+
+/// To the file at `path`, on every core when it's a regular file and the format allows, unless
+/// it's `--direct`.
+fn write_file(args: &Args, path: &Path) -> io::Result<()> {
+    // Mapping a file for writing takes reading it too
+    let file = OpenOptions::new()
+        .read(args.mmap)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    let target = Target::of(file.as_fd())?;
+    let mut capacity = buffer_size(target, file.as_raw_fd(), args.verbose);
+    if args.mmap {
+        // Each batch is copied from the one before it in the mapping, so keep them in cache
+        capacity = BUF_SIZE;
+    }
+    let parallel = args.bare_template();
+    if let (Target::File { .. }, Some((layout, preamble))) = (target, &parallel) {
+        let len = preamble.len() as u128 + buzz_len(layout, args.start..=args.end, args.stride)?;
+        reserve(file.as_fd(), 0, len, args.verbose)?;
+    }
+    match (target, parallel) {
+        (Target::File { .. }, _) if args.direct => {
+            if args.verbose {
+                eprintln!("firehose: direct backend, {capacity} byte buffers");
+            }
+            let out = Direct::new(file, capacity).map_err(|e| {
+                io::Error::new(e.kind(), format!("--direct: no O_DIRECT for {path:?}: {e}"))
+            })?;
+            write_to(args, capacity, out)
+        }
+        _ if args.direct => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--direct needs --output to be a regular file",
+        )),
+        (Target::File { .. }, Some((layout, preamble))) => {
+            let threads = args.threads();
+            let backend = match args.mmap {
+                true => "mmap",
+                false => "pwrite",
+            };
+            if args.verbose {
+                eprintln!(
+                    "firehose: {backend} backend, {capacity} byte buffers, {threads} threads"
+                );
+            }
+            file.write_all_at(&preamble, 0)?;
+            let range = args.start..=args.end;
+            let offset = preamble.len() as u64;
+            let buzz = match args.mmap {
+                true => mmap_buzz,
+                false => parallel_buzz,
+            };
+            buzz(
+                &layout,
+                range,
+                args.stride,
+                capacity,
+                threads,
+                &file,
+                offset,
+            )
+        }
+        _ if args.mmap => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--mmap needs --output to be a regular file",
+        )),
+        (Target::File { .. }, None) => {
+            if args.verbose {
+                eprintln!("firehose: pwrite backend, {capacity} byte buffers");
+            }
+            write_to(args, capacity, Pwrite::new(file, capacity)?)
+        }
+        _ => {
+            if args.verbose {
+                eprintln!("firehose: write backend, {capacity} byte buffers");
+            }
+            write_to(args, capacity, file)
+        }
+    }
+}
+
+/// Into a sealed memfd, for `args.exec` to read as its stdin.
+fn exec(args: &Args) -> io::Result<()> {
+    let (layout, preamble) = args.bare_template().unwrap();
+    let threads = args.threads();
+    let range = args.start..=args.end;
+    let file = sealed_buzz(
+        &layout,
+        &preamble,
+        range,
+        args.stride,
+        FILE_BUF_SIZE,
+        threads,
+    )?;
+    if args.verbose {
+        let len = file.metadata()?.len();
+        eprintln!("firehose: {len} bytes sealed into a memfd, on {threads} threads");
+    }
+    let (program, rest) = args.exec.split_first().unwrap();
+    let mut command = std::process::Command::new(program);
+    let mut child = spawn_with_stdin(command.args(rest), file)
+        .map_err(|e| io::Error::new(e.kind(), format!("{program}: {e}")))?;
+    let status = child.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("{program}: {status}"))),
+    }
+}
+
+fn run(args: &Args) -> io::Result<()> {
+    if let Some(path) = &args.output {
+        return write_file(args, path);
+    }
+    if !args.exec.is_empty() {
+        return exec(args);
+    }
+    let stdout = io::stdout();
+    let fd = stdout.as_raw_fd();
+    let target = Target::of(stdout.as_fd())?;
+    let capacity = buffer_size(target, fd, args.verbose);
+    if let (Target::File { .. }, Some((layout, preamble))) = (target, args.bare_template()) {
+        let mut file = File::from(stdout.as_fd().try_clone_to_owned()?);
+        let offset = file.stream_position()?;
+        let len = preamble.len() as u128 + buzz_len(&layout, args.start..=args.end, args.stride)?;
+        reserve(stdout.as_fd(), offset, len, args.verbose)?;
+    }
+    let backend = args.backend.unwrap_or(auto_backend(target));
+    if args.verbose {
+        let (name, _) = BACKENDS.iter().find(|&&(_, b)| b == backend).unwrap();
+        eprintln!("firehose: {name} backend, {capacity} byte buffers");
+    }
+    match backend {
+        Backend::Write => {}
+        Backend::Pwrite => {
+            let file = File::from(stdout.as_fd().try_clone_to_owned()?);
+            let out = Pwrite::new(file, capacity).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("--backend pwrite needs stdout to be a regular file: {e}"),
+                )
+            })?;
+            return write_to(args, capacity, out);
+        }
+        Backend::Vmsplice => {
+            let mut pipe = Vmsplice::new(fd, capacity).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("--backend vmsplice needs stdout to be a pipe: {e}"),
+                )
+            })?;
+            return template_to(args, &mut pipe);
+        }
+        Backend::Writev => {
+            let (layout, preamble) = args.template().unwrap();
+            // Unbuffered, so the preamble can't end up after the gathered writes
+            let mut out = File::from(stdout.as_fd().try_clone_to_owned()?);
+            out.write_all(&preamble)?;
+            return gather_buzz(
+                &layout,
+                args.start..=args.end,
+                args.stride,
+                capacity,
+                &mut out,
+            );
+        }
+        // Seccomp and old kernels turn io_uring off, which is no reason not to write
+        Backend::IoUring => match Uring::new(fd, capacity) {
+            Ok(mut uring) => return template_to(args, &mut uring),
+            Err(e) if args.verbose => eprintln!("firehose: no io_uring ({e}), writing instead"),
+            Err(_) => {}
+        },
+    }
+    write_to(args, capacity, stdout.lock())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprint!("firehose: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        // Whoever's reading hung up, that's fine
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("firehose: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Compact binary records.
+//!
+//! A stream starts with an 8 byte header: the magic `FZBZ`, then the format version as a u32 LE.
+//! After that, every line is one record, starting with a kind byte:
+//!
+//! | kind | line     | payload        |
+//! |------|----------|----------------|
+//! | 0    | number   | the n, u64 LE  |
+//! | 1    | Fizz     |                |
+//! | 2    | Buzz     |                |
+//! | 3    | FizzBuzz |                |
+
+use std::io::{self, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::buffer::Buffer;
+use crate::counter::{AsciiCounter, NumFormat};
+use crate::layout::Class;
+
+pub const MAGIC: &[u8; 4] = b"FZBZ";
+pub const VERSION: u32 = 1;
+const MAX_RECORD_LEN: usize = 9;
+
+fn header() -> [u8; 8] {
+    let mut header = [0; 8];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..].copy_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+pub fn binary_buzz<W: Write>(range: RangeInclusive<u64>, out: &mut W) -> io::Result<()> {
+    out.write_all(&header())?;
+    if range.is_empty() {
+        return Ok(());
+    }
+    let (mut n, end) = range.into_inner();
+    let mut buf = Buffer::new();
+    loop {
+        if buf.spare_capacity() < MAX_RECORD_LEN {
+            out.write_all(buf.view())?;
+            buf.clear();
+        }
+        let class = Class::of(n);
+        buf.write_all(&[class as u8])?;
+        if class == Class::Number {
+            buf.write_all(&n.to_le_bytes())?;
+        }
+        if n == end {
+            break;
+        }
+        n += 1;
+    }
+    out.write_all(buf.view())
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Turns records back into the plain text output.
+pub fn decode<R: Read, W: Write>(input: R, out: &mut W) -> io::Result<()> {
+    let mut input = BufReader::with_capacity(64 * 1024, input);
+    let mut head = [0; 8];
+    let read = input.read_exact(&mut head);
+    if read.is_err() || head != header() {
+        return Err(invalid(format!("not a version {VERSION} FizzBuzz stream")));
+    }
+
+    let mut buf = Buffer::new();
+    // Numbers are usually only a few apart, so we bump a counter rather than reformatting
+    let mut counter = AsciiCounter::new(0, NumFormat::default());
+    let mut last = 0;
+    loop {
+        if buf.spare_capacity() < 32 {
+            out.write_all(buf.view())?;
+            buf.clear();
+        }
+        let mut kind = [0];
+        match input.read(&mut kind)? {
+            0 => break,
+            _ => match kind[0] {
+                0 => {
+                    let mut n = [0; 8];
+                    input
+                        .read_exact(&mut n)
+                        .map_err(|_| invalid("truncated number record".to_string()))?;
+                    let n = u64::from_le_bytes(n);
+                    match n.checked_sub(last) {
+                        Some(incr @ 1..=9) => counter.bump(incr as u8),
+                        Some(0) => {}
+                        _ => counter = AsciiCounter::new(n, NumFormat::default()),
+                    }
+                    last = n;
+                    buf.write_all(counter.view_ascii())?;
+                    buf.write_all(b"\n")?;
+                }
+                1 => buf.write_all(b"Fizz\n")?,
+                2 => buf.write_all(b"Buzz\n")?,
+                3 => buf.write_all(b"FizzBuzz\n")?,
+                other => return Err(invalid(format!("unknown record kind {other}"))),
+            },
+        }
+    }
+    out.write_all(buf.view())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+    use crate::template::{Stride, fast_buzz};
+
+    #[test]
+    fn decode_gives_back_the_text() {
+        let layout = Layout::plain(NumFormat::default());
+        for range in [1..=100_000, 999_990..=1_000_010, u64::MAX - 100..=u64::MAX] {
+            let mut records = Vec::new();
+            binary_buzz(range.clone(), &mut records).unwrap();
+            let mut decoded = Vec::new();
+            decode(&records[..], &mut decoded).unwrap();
+            let mut expected = Vec::new();
+            let range = *range.start() as i128..=*range.end() as i128;
+            fast_buzz(&layout, range, Stride::ALL, 4096, &mut expected).unwrap();
+            assert!(decoded == expected);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+
+/// The default, a pipe's worth.
+pub const BUF_SIZE: usize = 64 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+#[derive(Clone)]
+#[repr(C, align(4096))]
+struct Page([u8; PAGE_SIZE]);
+
+/// Page aligned, so `vmsplice(2)` can map the data straight into a pipe.
+pub struct Buffer {
+    data: Box<[Page]>,
+    offset: usize,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::with_capacity(BUF_SIZE)
+    }
+    /// Rounded up to whole pages.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let pages = capacity.div_ceil(PAGE_SIZE);
+        Buffer {
+            data: vec![Page([0; PAGE_SIZE]); pages].into_boxed_slice(),
+            offset: 0,
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.data.len() * PAGE_SIZE
+    }
+    pub fn len(&self) -> usize {
+        self.offset
+    }
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+    pub fn clear(&mut self) {
+        self.offset = 0;
+    }
+    pub fn spare_capacity(&self) -> usize {
+        self.capacity() - self.offset
+    }
+    pub fn view(&self) -> &[u8] {
+        &self.bytes()[..self.offset]
+    }
+    /// The filled part, for patching in place.
+    pub fn view_mut(&mut self) -> &mut [u8] {
+        let offset = self.offset;
+        &mut self.bytes_mut()[..offset]
+    }
+    /// Start of the whole capacity, for handing the buffer to the kernel ahead of time.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.data.as_ptr().cast()
+    }
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.data.as_mut_ptr().cast()
+    }
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.as_ptr(), self.capacity()) }
+    }
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.capacity()) }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len();
+        if n <= self.spare_capacity() {
+            let offset = self.offset;
+            self.bytes_mut()[offset..offset + n].copy_from_slice(buf);
+            self.offset += n;
+            Ok(n)
+        } else {
+            Err(io::Error::other("Buffer overrun"))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Adds `addend` to the digit at `offset`, carrying into the ones before it.
+///
+/// # Safety
+///
+/// `offset` has to be in `bytes`, with a digit that isn't a 9 somewhere before it in `bytes`, so
+/// the carry stops inside the slice. A number that keeps its width always has one.
+#[inline(always)]
+pub(crate) unsafe fn ripple_carry_add_ascii(bytes: &mut [u8], offset: usize, addend: u8) {
+    debug_assert!(offset < bytes.len());
+    unsafe {
+        let mut digit = bytes.as_mut_ptr().add(offset);
+        *digit += addend;
+
+        if *digit > b'9' {
+            *digit -= 10;
+            loop {
+                digit = digit.sub(1);
+                if *digit == b'9' {
+                    *digit = b'0'
+                } else {
+                    *digit += 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Same as [`ripple_carry_add_ascii`], but carries hop over the thousands separators.
+///
+/// # Safety
+///
+/// As for [`ripple_carry_add_ascii`].
+#[inline(always)]
+pub(crate) unsafe fn ripple_carry_add_ascii_grouped(
+    bytes: &mut [u8],
+    offset: usize,
+    addend: u8,
+    sep: u8,
+) {
+    debug_assert!(offset < bytes.len());
+    unsafe {
+        let mut digit = bytes.as_mut_ptr().add(offset);
+        *digit += addend;
+
+        if *digit > b'9' {
+            *digit -= 10;
+            loop {
+                digit = digit.sub(1);
+                if *digit == b'9' {
+                    *digit = b'0'
+                } else if *digit != sep {
+                    *digit += 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The other way round from [`ripple_carry_add_ascii`]: borrows ripple up instead.
+///
+/// The number mustn't lose a digit, so the borrow always stops at a nonzero digit.
+///
+/// # Safety
+///
+/// `offset` has to be in `bytes`, with a digit that isn't a 0 somewhere before it in `bytes`, so
+/// the borrow stops inside the slice.
+#[inline(always)]
+pub(crate) unsafe fn ripple_borrow_sub_ascii(bytes: &mut [u8], offset: usize, subtrahend: u8) {
+    debug_assert!(offset < bytes.len());
+    unsafe {
+        let mut digit = bytes.as_mut_ptr().add(offset);
+        *digit -= subtrahend;
+
+        if *digit < b'0' {
+            *digit += 10;
+            loop {
+                digit = digit.sub(1);
+                if *digit == b'0' {
+                    *digit = b'9'
+                } else {
+                    *digit -= 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Same as [`ripple_borrow_sub_ascii`], but borrows hop over the thousands separators.
+///
+/// # Safety
+///
+/// As for [`ripple_borrow_sub_ascii`].
+#[inline(always)]
+pub(crate) unsafe fn ripple_borrow_sub_ascii_grouped(
+    bytes: &mut [u8],
+    offset: usize,
+    subtrahend: u8,
+    sep: u8,
+) {
+    debug_assert!(offset < bytes.len());
+    unsafe {
+        let mut digit = bytes.as_mut_ptr().add(offset);
+        *digit -= subtrahend;
+
+        if *digit < b'0' {
+            *digit += 10;
+            loop {
+                digit = digit.sub(1);
+                if *digit == b'0' {
+                    *digit = b'9'
+                } else if *digit != sep {
+                    *digit -= 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrows_ripple_up_to_the_first_nonzero_digit() {
+        let cases: [(&[u8], usize, u8, &[u8]); 4] = [
+            (b"1000\n", 3, 1, b"0999\n"),
+            (b"1002\n", 3, 7, b"0995\n"),
+            (b"1000\n", 2, 1, b"0990\n"),
+            (b"2050\n", 3, 9, b"2041\n"),
+        ];
+        for (before, offset, subtrahend, after) in cases {
+            let mut bytes = before.to_vec();
+            unsafe { ripple_borrow_sub_ascii(&mut bytes, offset, subtrahend) };
+            assert_eq!(bytes, after);
+        }
+    }
+
+    #[test]
+    fn grouped_borrows_hop_over_separators() {
+        let cases: [(&[u8], usize, u8, &[u8]); 3] = [
+            (b"1,000\n", 4, 1, b"0,999\n"),
+            (b"1,000,000\n", 8, 1, b"0,999,999\n"),
+            (b"1,000,000\n", 6, 3, b"0,999,700\n"),
+        ];
+        for (before, offset, subtrahend, after) in cases {
+            let mut bytes = before.to_vec();
+            unsafe { ripple_borrow_sub_ascii_grouped(&mut bytes, offset, subtrahend, b',') };
+            assert_eq!(bytes, after);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Template-plus-range fixtures.
+//!
+//! The output of the template formats is completely determined by the line templates and the
+//! range, so that's all we store. The line templates are the same for every digit width, the
+//! expander instantiates them per width just like [`fast_buzz`] does. All integers are LE.
+//!
+//! ```text
+//! magic     b"FZBT"
+//! version   u32
+//! start     i128
+//! end       i128, inclusive
+//! step      u64, only every step-th line is written
+//! offset    u64, lines skipped before the first one that's written
+//! order     u8, 0 = counting up from start, 1 = counting down from end
+//! preamble  u32 length, then that many bytes, written once before the first line
+//! lines     4 times, for Number, Fizz, Buzz and FizzBuzz:
+//!             u8 piece count, then per piece:
+//!               0, u16 length, bytes   a literal
+//!               1, field               a number
+//!
+//! field     value u8, 0 = the number, 1 = the line number
+//!           pad   u8, 0 = none, 1 = zeros, 2 = spaces
+//!           width u8, digits to pad out to
+//!           sep   u8, thousands separator, 0 = none
+//! ```
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::buffer::BUF_SIZE;
+use crate::counter::{MAX_DIGITS, NumFormat, Pad};
+use crate::layout::{Class, Field, Layout, Piece, Value};
+use crate::template::{Stride, fast_buzz};
+
+pub const MAGIC: &[u8; 4] = b"FZBT";
+pub const VERSION: u32 = 5;
+/// Keeps every line comfortably inside a `Buffer`.
+const MAX_LINE_LEN: usize = 4096;
+
+pub fn compress<W: Write>(
+    layout: &Layout,
+    preamble: &[u8],
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&range.start().to_le_bytes());
+    bytes.extend_from_slice(&range.end().to_le_bytes());
+    bytes.extend_from_slice(&stride.step.to_le_bytes());
+    bytes.extend_from_slice(&stride.offset.to_le_bytes());
+    bytes.push(stride.descending as u8);
+    bytes.extend_from_slice(&(preamble.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(preamble);
+    for class in Class::ALL {
+        let line = layout.line(class);
+        bytes.push(u8::try_from(line.len()).unwrap());
+        for piece in line {
+            match piece {
+                Piece::Lit(lit) => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(&u16::try_from(lit.len()).unwrap().to_le_bytes());
+                    bytes.extend_from_slice(lit);
+                }
+                Piece::Num(field) => {
+                    bytes.push(1);
+                    push_field(*field, &mut bytes);
+                }
+            }
+        }
+    }
+    out.write_all(&bytes)
+}
+
+fn push_field(field: Field, bytes: &mut Vec<u8>) {
+    bytes.push(match field.value {
+        Value::N => 0,
+        Value::LineNo => 1,
+    });
+    bytes.push(match field.fmt.pad {
+        Pad::None => 0,
+        Pad::Zeros => 1,
+        Pad::Spaces => 2,
+    });
+    bytes.push(field.fmt.width as u8);
+    bytes.push(field.fmt.sep.unwrap_or(0));
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("truncated fixture"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn field(&mut self) -> io::Result<Field> {
+        let value = match self.u8()? {
+            0 => Value::N,
+            1 => Value::LineNo,
+            _ => return Err(invalid("unknown field value")),
+        };
+        let pad = match self.u8()? {
+            0 => Pad::None,
+            1 => Pad::Zeros,
+            2 => Pad::Spaces,
+            _ => return Err(invalid("unknown padding")),
+        };
+        let width = match self.u8()? as usize {
+            width @ 0..=MAX_DIGITS => width,
+            _ => return Err(invalid("field too wide")),
+        };
+        let sep = match self.u8()? {
+            0 => None,
+            sep if sep.is_ascii_digit() => return Err(invalid("digit as thousands separator")),
+            sep => Some(sep),
+        };
+        Ok(Field {
+            value,
+            fmt: NumFormat { pad, width, sep },
+        })
+    }
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn i128(&mut self) -> io::Result<i128> {
+        Ok(i128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+}
+
+/// Reads a fixture and writes out the stream it describes.
+pub fn expand<R: Read, W: Write>(mut input: R, out: &mut W) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let mut cursor = Cursor(&bytes);
+    if cursor.take(4).ok() != Some(MAGIC) || cursor.u32()? != VERSION {
+        return Err(invalid("not a FizzBuzz template fixture"));
+    }
+    let start = cursor.i128()?;
+    let end = cursor.i128()?;
+    if start.unsigned_abs() > u64::MAX as u128 || end.unsigned_abs() > u64::MAX as u128 {
+        return Err(invalid("range out of bounds"));
+    }
+    let stride = Stride {
+        step: cursor.u64()?,
+        offset: cursor.u64()?,
+        descending: match cursor.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid("unknown order")),
+        },
+    };
+    if stride.step == 0 {
+        return Err(invalid("zero step"));
+    }
+    let preamble_len = cursor.u32()? as usize;
+    let preamble = cursor.take(preamble_len)?;
+    let mut lines: [Vec<Piece>; 4] = Default::default();
+    for line in lines.iter_mut() {
+        for _ in 0..cursor.u8()? {
+            line.push(match cursor.u8()? {
+                0 => {
+                    let len = cursor.u16()? as usize;
+                    Piece::Lit(cursor.take(len)?.to_vec())
+                }
+                1 => Piece::Num(cursor.field()?),
+                _ => return Err(invalid("unknown piece")),
+            });
+        }
+    }
+    if !cursor.0.is_empty() {
+        return Err(invalid("trailing bytes after fixture"));
+    }
+
+    let layout = Layout::new(lines);
+    if layout.max_line_len() > MAX_LINE_LEN {
+        return Err(invalid("lines too long"));
+    }
+    out.write_all(preamble)?;
+    fast_buzz(&layout, start..=end, stride, BUF_SIZE, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_gives_back_the_stream() {
+        let fmt = NumFormat {
+            pad: Pad::Zeros,
+            width: 7,
+            sep: Some(b','),
+        };
+        let layouts = [
+            (Layout::plain(fmt), Vec::new()),
+            (Layout::jsonl().numbered(), Vec::new()),
+            (Layout::delimited(b','), Layout::delimited_header(b',')),
+        ];
+        let stride = |step, offset, descending| Stride {
+            step,
+            offset,
+            descending,
+        };
+        let cases = [
+            (1..=100_000, Stride::ALL),
+            (-12_345..=678, stride(1, 0, false)),
+            (-5_000..=-1, stride(1, 0, true)),
+            (995..=1_000_020, stride(1, 0, true)),
+            (-70_000..=70_000, stride(7, 3, false)),
+            (1..=1_000_000, stride(11, 5, true)),
+        ];
+        for (layout, preamble) in &layouts {
+            for (range, stride) in cases.clone() {
+                let mut fixture = Vec::new();
+                compress(layout, preamble, range.clone(), stride, &mut fixture).unwrap();
+                let mut expanded = Vec::new();
+                // The sign would make a padded number a byte wider
+                let padded = |f: &Field| f.value == Value::N && f.fmt.pad != Pad::None;
+                if *range.start() < 0 && layout.fields().iter().any(padded) {
+                    let e = expand(&fixture[..], &mut expanded).unwrap_err();
+                    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+                    continue;
+                }
+                expand(&fixture[..], &mut expanded).unwrap();
+                let mut expected = preamble.clone();
+                fast_buzz(layout, range, stride, 4096, &mut expected).unwrap();
+                assert!(expanded == expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+/// `u64::MAX` is 20 digits, so that's the widest number we'll ever print.
+pub const MAX_DIGITS: usize = 20;
+/// Room for a separator between every group of three digits.
+const MAX_WIDTH: usize = MAX_DIGITS + (MAX_DIGITS - 1) / 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pad {
+    /// `42`
+    #[default]
+    None,
+    /// `00000000000000000042`
+    Zeros,
+    /// `                  42`
+    Spaces,
+}
+
+impl Pad {
+    fn fill(self) -> u8 {
+        match self {
+            Pad::Zeros => b'0',
+            Pad::None | Pad::Spaces => b' ',
+        }
+    }
+}
+
+/// How a number is spelled out in ASCII.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NumFormat {
+    pub pad: Pad,
+    /// Digits to pad out to. Numbers with more digits than this just take up more room.
+    pub width: usize,
+    /// Thousands separator, as in `1,234,567`.
+    pub sep: Option<u8>,
+}
+
+impl NumFormat {
+    /// Every line the same width, all the way up to `u64::MAX`.
+    pub const fn padded(pad: Pad) -> NumFormat {
+        NumFormat {
+            pad,
+            width: MAX_DIGITS,
+            sep: None,
+        }
+    }
+    const fn min_digits(self) -> usize {
+        match self.pad {
+            Pad::Zeros | Pad::Spaces if self.width > 1 => self.width,
+            _ => 1,
+        }
+    }
+    /// Bytes taken up by a number of `digits` digits.
+    pub const fn len(self, digits: usize) -> usize {
+        let min_digits = self.min_digits();
+        let digits = if digits > min_digits {
+            digits
+        } else {
+            min_digits
+        };
+        self.byte_offset(digits - 1) + 1
+    }
+    pub const fn max_len(self) -> usize {
+        self.len(MAX_DIGITS)
+    }
+    /// Distance from the ones digit to the digit for `10^exp`.
+    pub const fn byte_offset(self, exp: usize) -> usize {
+        match self.sep {
+            Some(_) => exp + exp / 3,
+            None => exp,
+        }
+    }
+    /// First number that renders just like `n`, only with different digits.
+    pub const fn segment_start(self, n: u64) -> u64 {
+        let digits = count_digits(n);
+        match self.pad {
+            Pad::Zeros if digits <= self.width => 0,
+            _ if digits == 1 => 0,
+            _ => 10_u64.pow(digits as u32 - 1),
+        }
+    }
+    /// Last number that renders just like `n`, only with different digits.
+    ///
+    /// Padding zeros are digits like any other, so carries can run straight through them.
+    pub const fn segment_end(self, n: u64) -> u64 {
+        let digits = count_digits(n);
+        let digits = match self.pad {
+            Pad::Zeros if digits <= self.width => self.width,
+            _ => digits,
+        };
+        if digits >= MAX_DIGITS {
+            u64::MAX
+        } else {
+            10_u64.pow(digits as u32) - 1
+        }
+    }
+    fn is_sep_slot(self, byte_offset: usize) -> bool {
+        self.sep.is_some() && byte_offset % 4 == 3
+    }
+}
+
+pub struct AsciiCounter {
+    digits: [u8; MAX_WIDTH],
+    head: usize,
+    fmt: NumFormat,
+}
+
+impl AsciiCounter {
+    pub fn new(start: u64, fmt: NumFormat) -> AsciiCounter {
+        let mut digits = [fmt.pad.fill(); MAX_WIDTH];
+        if let (Pad::Zeros, Some(sep)) = (fmt.pad, fmt.sep) {
+            for exp in (3..MAX_DIGITS).step_by(3) {
+                digits[MAX_WIDTH - fmt.byte_offset(exp)] = sep;
+            }
+        }
+        let mut head = MAX_WIDTH;
+        let mut n = start;
+        for exp in 0.. {
+            if exp > 0
+                && exp % 3 == 0
+                && let Some(sep) = fmt.sep
+            {
+                head -= 1;
+                digits[head] = sep;
+            }
+            head -= 1;
+            digits[head] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        AsciiCounter { digits, head, fmt }
+    }
+    pub fn bump(&mut self, incr: u8) {
+        self.add_digit(0, incr);
+    }
+    /// Adds any amount, one decimal digit at a time.
+    pub fn add(&mut self, mut incr: u64) {
+        let mut exp = 0;
+        while incr > 0 {
+            let digit = (incr % 10) as u8;
+            if digit > 0 {
+                self.add_digit(exp, digit);
+            }
+            incr /= 10;
+            exp += 1;
+        }
+    }
+    /// Adds `digit * 10^exp`.
+    fn add_digit(&mut self, exp: usize, digit: u8) {
+        let at = MAX_WIDTH - 1 - self.fmt.byte_offset(exp);
+        // Skipping past the head, so zeros and separators have to fill in the gap
+        for i in at..self.head {
+            self.digits[i] = match self.fmt.is_sep_slot(MAX_WIDTH - 1 - i) {
+                true => self.fmt.sep.unwrap(),
+                false => b'0',
+            };
+        }
+        self.head = std::cmp::min(self.head, at);
+
+        let place = &mut self.digits[at];
+        *place += digit;
+
+        if *place > b'9' {
+            *place -= 10;
+            for (i, digit) in self.digits.iter_mut().enumerate().take(at).rev() {
+                self.head = std::cmp::min(self.head, i);
+                if self.fmt.is_sep_slot(MAX_WIDTH - 1 - i) {
+                    *digit = self.fmt.sep.unwrap();
+                    continue;
+                }
+                match *digit {
+                    b'9' => *digit = b'0',
+                    // Carrying into the padding
+                    b' ' => {
+                        *digit = b'1';
+                        break;
+                    }
+                    _ => {
+                        *digit += 1;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    /// Subtracts any amount, one decimal digit at a time. The result mustn't go below zero.
+    pub fn sub(&mut self, mut decr: u64) {
+        let mut exp = 0;
+        while decr > 0 {
+            let digit = (decr % 10) as u8;
+            if digit > 0 {
+                self.sub_digit(exp, digit);
+            }
+            decr /= 10;
+            exp += 1;
+        }
+    }
+    /// Subtracts `digit * 10^exp`.
+    fn sub_digit(&mut self, exp: usize, digit: u8) {
+        let at = MAX_WIDTH - 1 - self.fmt.byte_offset(exp);
+        let place = &mut self.digits[at];
+        *place -= digit;
+
+        if *place < b'0' {
+            *place += 10;
+            for (i, digit) in self.digits.iter_mut().enumerate().take(at).rev() {
+                if self.fmt.is_sep_slot(MAX_WIDTH - 1 - i) {
+                    continue;
+                }
+                match *digit {
+                    b'0' => *digit = b'9',
+                    _ => {
+                        *digit -= 1;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Dropping leading zeros, and the separators that went with them
+        while self.head < MAX_WIDTH - 1
+            && (self.digits[self.head] == b'0' || self.fmt.is_sep_slot(MAX_WIDTH - 1 - self.head))
+        {
+            if self.fmt.pad != Pad::Zeros {
+                self.digits[self.head] = b' ';
+            }
+            self.head += 1;
+        }
+    }
+    pub fn view_ascii(&self) -> &[u8] {
+        match self.fmt.pad {
+            Pad::None => &self.digits[self.head..],
+            Pad::Zeros | Pad::Spaces => {
+                let padded = MAX_WIDTH - self.fmt.len(1);
+                &self.digits[std::cmp::min(self.head, padded)..]
+            }
+        }
+    }
+}
+
+pub const fn count_digits(n: u64) -> usize {
+    match n.checked_ilog10() {
+        Some(log) => log as usize + 1,
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// How `fmt` spells `n`, worked out with `format!`.
+    pub(crate) fn spelled(n: u64, fmt: NumFormat) -> String {
+        let digits = match fmt.pad {
+            Pad::Zeros => format!("{n:0>0$}", fmt.width),
+            Pad::None | Pad::Spaces => n.to_string(),
+        };
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if let Some(sep) = fmt.sep
+                && i > 0
+                && (digits.len() - i) % 3 == 0
+            {
+                grouped.push(sep as char);
+            }
+            grouped.push(digit);
+        }
+        match fmt.pad {
+            Pad::Spaces => format!("{grouped:>0$}", fmt.len(1)),
+            Pad::None | Pad::Zeros => grouped,
+        }
+    }
+
+    #[test]
+    fn counts_down_across_widths() {
+        let fmt = |pad, width, sep| NumFormat { pad, width, sep };
+        let fmts = [
+            NumFormat::default(),
+            fmt(Pad::None, 0, Some(b',')),
+            fmt(Pad::Zeros, 7, None),
+            fmt(Pad::Zeros, 7, Some(b',')),
+            fmt(Pad::Spaces, 5, Some(b',')),
+        ];
+        for fmt in fmts {
+            // Across 1000 to 999 and 1,000,000 to 999,999, and all the way down to 0
+            for (start, decr) in [(1_005, 1), (1_000_005, 1), (1_000_100, 7), (2_460, 123)] {
+                let mut counter = AsciiCounter::new(start, fmt);
+                let mut n = start;
+                while n >= decr && start - n < 30 * decr {
+                    counter.sub(decr);
+                    n -= decr;
+                    assert_eq!(counter.view_ascii(), spelled(n, fmt).as_bytes());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Output to a regular file with `O_DIRECT`, around the page cache.
+//!
+//! A fixture bigger than memory, written through the page cache, evicts everything else on the
+//! machine on its way to the disk. Direct writes go from our buffers to the disk without stopping
+//! there, but only whole blocks from block-aligned memory to block-aligned places in the file.
+//! [`Buffer`]s are page aligned, and written out on page boundaries of the file. Whatever doesn't
+//! fill a block, at the start of a file that wasn't empty or at the very end, is written with
+//! `O_DIRECT` turned off for it.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+
+use crate::pwrite::{Blocks, WriteAt};
+use crate::sys;
+
+/// What direct writes line up on. 512 bytes is enough for most disks, but not all.
+const BLOCK_SIZE: usize = 4096;
+
+pub type Direct = Blocks<DirectFile>;
+
+impl Direct {
+    /// Starts at the file's current position, with buffers of `capacity` bytes, rounded up to
+    /// whole blocks. Fails if the file system can't do `O_DIRECT`.
+    pub fn new(file: File, capacity: usize) -> io::Result<Direct> {
+        sys::set_direct(file.as_raw_fd(), true)?;
+        let file = DirectFile { file, direct: true };
+        Blocks::with(file, capacity.next_multiple_of(BLOCK_SIZE))
+    }
+}
+
+pub struct DirectFile {
+    file: File,
+    /// Whether `O_DIRECT` is on for `file` right now.
+    direct: bool,
+}
+
+impl DirectFile {
+    fn set_direct(&mut self, direct: bool) -> io::Result<()> {
+        if self.direct != direct {
+            sys::set_direct(self.file.as_raw_fd(), direct)?;
+            self.direct = direct;
+        }
+        Ok(())
+    }
+}
+
+impl WriteAt for DirectFile {
+    fn file(&self) -> &File {
+        &self.file
+    }
+    fn write_all_at(&mut self, bytes: &[u8], offset: u64) -> io::Result<()> {
+        let blocks = match offset as usize % BLOCK_SIZE {
+            0 => bytes.len() / BLOCK_SIZE * BLOCK_SIZE,
+            _ => 0,
+        };
+        if blocks > 0 {
+            self.set_direct(true)?;
+            self.file.write_all_at(&bytes[..blocks], offset)?;
+        }
+        if blocks < bytes.len() {
+            self.set_direct(false)?;
+            self.file
+                .write_all_at(&bytes[blocks..], offset + blocks as u64)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, Write};
+
+    #[test]
+    fn unaligned_head_and_tail_go_around_o_direct() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("firehose-{name}-{}", std::process::id()));
+        let (direct_path, plain_path) = (path("direct"), path("plain"));
+        let mut bytes = Vec::new();
+        for i in 0..20_000_u32 {
+            writeln!(bytes, "{i}").unwrap();
+        }
+
+        let mut plain = File::create(&plain_path).unwrap();
+        plain.write_all(b"not aligned\n").unwrap();
+        plain.write_all(&bytes).unwrap();
+
+        let mut file = File::create(&direct_path).unwrap();
+        file.write_all(b"not aligned\n").unwrap();
+        // Not every file system does O_DIRECT
+        if let Ok(mut out) = Direct::new(file.try_clone().unwrap(), 3 * BLOCK_SIZE) {
+            // In odd pieces, so buffers fill across writes
+            for piece in bytes.chunks(1000) {
+                out.write_all(piece).unwrap();
+            }
+            drop(out);
+            assert!(std::fs::read(&direct_path).unwrap() == std::fs::read(&plain_path).unwrap());
+            assert_eq!(
+                file.stream_position().unwrap(),
+                plain.stream_position().unwrap()
+            );
+            assert_ne!(plain.stream_position().unwrap() as usize % BLOCK_SIZE, 0);
+        }
+        std::fs::remove_file(direct_path).unwrap();
+        std::fs::remove_file(plain_path).unwrap();
+    }
+}
@@ -0,0 +1,448 @@
+//! A std-only gzip writer.
+//!
+//! Greedy LZ77 with a single-probe hash table, coded with the fixed Huffman codes from RFC 1951.
+//! Consecutive FizzBuzz lines share almost everything, so nearly every line turns into one or
+//! two back-references, and dynamic Huffman trees wouldn't buy us much on top of that.
+
+use std::io::{self, Write};
+
+/// Furthest a back-reference can reach.
+const WINDOW: usize = 32 * 1024;
+/// Input bytes per DEFLATE block.
+const BLOCK: usize = 256 * 1024;
+/// We hash four bytes at a time, so that's the shortest match we look for.
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 16;
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// A bit pattern as it goes into the stream, LSB first.
+#[derive(Clone, Copy, Default)]
+struct Code {
+    bits: u32,
+    len: u32,
+}
+
+fn reverse(code: u32, len: u32) -> u32 {
+    code.reverse_bits() >> (32 - len)
+}
+
+/// The fixed literal/length code, RFC 1951 section 3.2.6.
+fn fixed_code(sym: u32) -> Code {
+    let (code, len) = match sym {
+        0..=143 => (0x30 + sym, 8),
+        144..=255 => (0x190 + sym - 144, 9),
+        256..=279 => (sym - 256, 7),
+        _ => (0xc0 + sym - 280, 8),
+    };
+    Code {
+        bits: reverse(code, len),
+        len,
+    }
+}
+
+/// Everything about coding a symbol is looked up, extra bits included.
+struct Tables {
+    literals: [Code; 257],
+    /// Indexed by match length.
+    lengths: [Code; MAX_MATCH + 1],
+    /// Indexed by distance.
+    distances: Vec<Code>,
+}
+
+impl Tables {
+    fn new() -> Tables {
+        let mut literals = [Code::default(); 257];
+        for (sym, code) in literals.iter_mut().enumerate() {
+            *code = fixed_code(sym as u32);
+        }
+
+        let mut lengths = [Code::default(); MAX_MATCH + 1];
+        for (i, (&base, &extra)) in LEN_BASE.iter().zip(&LEN_EXTRA).enumerate() {
+            let code = fixed_code(257 + i as u32);
+            for offset in 0..1 << extra {
+                let Some(entry) = lengths.get_mut(base as usize + offset) else {
+                    break;
+                };
+                *entry = Code {
+                    bits: code.bits | (offset as u32) << code.len,
+                    len: code.len + extra as u32,
+                };
+            }
+        }
+
+        let mut distances = vec![Code::default(); WINDOW + 1];
+        for (i, (&base, &extra)) in DIST_BASE.iter().zip(&DIST_EXTRA).enumerate() {
+            for offset in 0..1 << extra {
+                distances[base as usize + offset] = Code {
+                    bits: reverse(i as u32, 5) | (offset as u32) << 5,
+                    len: 5 + extra as u32,
+                };
+            }
+        }
+
+        Tables {
+            literals,
+            lengths,
+            distances,
+        }
+    }
+}
+
+struct BitWriter {
+    acc: u64,
+    n: u32,
+    out: Vec<u8>,
+}
+
+impl BitWriter {
+    #[inline(always)]
+    fn put(&mut self, code: Code) {
+        self.acc |= (code.bits as u64) << self.n;
+        self.n += code.len;
+        if self.n >= 32 {
+            self.out.extend_from_slice(&(self.acc as u32).to_le_bytes());
+            self.acc >>= 32;
+            self.n -= 32;
+        }
+    }
+    /// Pads out to a byte boundary.
+    fn align(&mut self) {
+        while self.n > 0 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n = self.n.saturating_sub(8);
+        }
+    }
+}
+
+pub struct Crc32 {
+    tables: Box<[[u32; 256]; 8]>,
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        let mut tables = Box::new([[0; 256]; 8]);
+        for i in 0..256 {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    crc >> 1 ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+            tables[0][i] = crc;
+        }
+        for i in 0..256 {
+            for t in 1..8 {
+                let prev = tables[t - 1][i];
+                tables[t][i] = prev >> 8 ^ tables[0][prev as usize & 0xff];
+            }
+        }
+        Crc32 { tables, crc: !0 }
+    }
+    /// Slice-by-8.
+    pub fn update(&mut self, bytes: &[u8]) {
+        let t = &self.tables;
+        let mut crc = self.crc;
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let lo = u32::from_le_bytes(chunk[..4].try_into().unwrap()) ^ crc;
+            let hi = u32::from_le_bytes(chunk[4..].try_into().unwrap());
+            crc = t[7][lo as usize & 0xff]
+                ^ t[6][(lo >> 8) as usize & 0xff]
+                ^ t[5][(lo >> 16) as usize & 0xff]
+                ^ t[4][(lo >> 24) as usize]
+                ^ t[3][hi as usize & 0xff]
+                ^ t[2][(hi >> 8) as usize & 0xff]
+                ^ t[1][(hi >> 16) as usize & 0xff]
+                ^ t[0][(hi >> 24) as usize];
+        }
+        for &byte in chunks.remainder() {
+            crc = crc >> 8 ^ t[0][(crc as u8 ^ byte) as usize];
+        }
+        self.crc = crc;
+    }
+    pub fn sum(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compresses everything written to it into a gzip stream.
+///
+/// Nothing is written after the last block until [`GzEncoder::finish`] is called.
+pub struct GzEncoder<W: Write> {
+    inner: W,
+    tables: Tables,
+    /// Up to a window of history, followed by input we haven't compressed yet.
+    data: Vec<u8>,
+    pending: usize,
+    /// Stream position of `data[0]`.
+    base: usize,
+    /// Stream position + 1 of the last time we saw a hash, 0 for never.
+    head: Vec<usize>,
+    bits: BitWriter,
+    crc: Crc32,
+    size: u32,
+}
+
+impl<W: Write> GzEncoder<W> {
+    pub fn new(mut inner: W) -> io::Result<GzEncoder<W>> {
+        // No name, no mtime, unknown OS
+        inner.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+        Ok(GzEncoder {
+            inner,
+            tables: Tables::new(),
+            data: Vec::with_capacity(WINDOW + 2 * BLOCK),
+            pending: 0,
+            base: 0,
+            head: vec![0; 1 << HASH_BITS],
+            bits: BitWriter {
+                acc: 0,
+                n: 0,
+                out: Vec::with_capacity(2 * BLOCK),
+            },
+            crc: Crc32::new(),
+            size: 0,
+        })
+    }
+
+    #[inline(always)]
+    fn hash(bytes: &[u8]) -> usize {
+        let word = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        (word.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn compress_block(&mut self, last: bool) -> io::Result<()> {
+        // BFINAL, then BTYPE 01 for the fixed codes
+        self.bits.put(Code {
+            bits: last as u32 | 1 << 1,
+            len: 3,
+        });
+
+        let data = &self.data;
+        let end = data.len();
+        let mut i = self.pending;
+        while i < end {
+            if i + MIN_MATCH <= end {
+                let h = Self::hash(&data[i..]);
+                let candidate = self.head[h];
+                self.head[h] = self.base + i + 1;
+                if candidate > self.base && self.base + i + 1 - candidate <= WINDOW {
+                    let c = candidate - 1 - self.base;
+                    if data[c..c + MIN_MATCH] == data[i..i + MIN_MATCH] {
+                        let max = std::cmp::min(MAX_MATCH, end - i);
+                        let mut len = MIN_MATCH;
+                        while len < max && data[c + len] == data[i + len] {
+                            len += 1;
+                        }
+                        self.bits.put(self.tables.lengths[len]);
+                        self.bits.put(self.tables.distances[i - c]);
+                        for j in i + 1..std::cmp::min(i + len, end - MIN_MATCH + 1) {
+                            self.head[Self::hash(&data[j..])] = self.base + j + 1;
+                        }
+                        i += len;
+                        continue;
+                    }
+                }
+            }
+            self.bits.put(self.tables.literals[data[i] as usize]);
+            i += 1;
+        }
+        self.bits.put(self.tables.literals[256]);
+        self.pending = end;
+
+        if self.data.len() > WINDOW {
+            let cut = self.data.len() - WINDOW;
+            self.data.drain(..cut);
+            self.base += cut;
+            self.pending -= cut;
+        }
+        if last {
+            self.bits.align();
+        }
+        self.inner.write_all(&self.bits.out)?;
+        self.bits.out.clear();
+        Ok(())
+    }
+
+    /// Writes the final block and the trailer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress_block(true)?;
+        self.inner.write_all(&self.crc.sum().to_le_bytes())?;
+        self.inner.write_all(&self.size.to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.crc.update(buf);
+        self.size = self.size.wrapping_add(buf.len() as u32);
+        if self.data.len() - self.pending >= BLOCK {
+            self.compress_block(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Ends the current block and byte-aligns the stream with an empty stored block, like zlib's
+    /// `Z_SYNC_FLUSH`, so everything so far can be decompressed.
+    fn flush(&mut self) -> io::Result<()> {
+        self.compress_block(false)?;
+        self.bits.put(Code { bits: 0, len: 3 });
+        self.bits.align();
+        self.bits.out.extend_from_slice(&[0, 0, 0xff, 0xff]);
+        self.inner.write_all(&self.bits.out)?;
+        self.bits.out.clear();
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::NumFormat;
+    use crate::layout::Layout;
+    use crate::template::{Stride, fast_buzz};
+
+    /// Reads the stream LSB first, as DEFLATE packs it.
+    struct Bits<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl Bits<'_> {
+        fn get(&mut self, n: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..n {
+                let bit = self.bytes[self.pos / 8] >> (self.pos % 8) & 1;
+                value |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            value
+        }
+        /// Huffman codes go in MSB first.
+        fn code(&mut self, n: u32, mut code: u32) -> u32 {
+            for _ in 0..n {
+                code = code << 1 | self.get(1);
+            }
+            code
+        }
+        fn literal_length(&mut self) -> u32 {
+            let code = self.code(7, 0);
+            if code < 0x18 {
+                return 256 + code;
+            }
+            let code = self.code(1, code);
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + self.code(1, code) - 0x190,
+            }
+        }
+    }
+
+    /// Just what [`GzEncoder`] writes: fixed Huffman blocks, and empty stored ones.
+    fn gunzip(gz: &[u8]) -> Vec<u8> {
+        assert_eq!(gz[..4], [0x1f, 0x8b, 8, 0]);
+        let mut bits = Bits {
+            bytes: &gz[10..],
+            pos: 0,
+        };
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let last = bits.get(1) == 1;
+            match bits.get(2) {
+                0 => {
+                    bits.pos = bits.pos.next_multiple_of(8);
+                    let len = bits.get(16);
+                    assert_eq!(bits.get(16), !len & 0xffff);
+                    for _ in 0..len {
+                        out.push(bits.get(8) as u8);
+                    }
+                }
+                1 => loop {
+                    let sym = bits.literal_length() as usize;
+                    match sym {
+                        0..=255 => out.push(sym as u8),
+                        256 => break,
+                        _ => {
+                            let i = sym - 257;
+                            let len = LEN_BASE[i] as usize + bits.get(LEN_EXTRA[i] as u32) as usize;
+                            let d = bits.code(5, 0) as usize;
+                            let dist =
+                                DIST_BASE[d] as usize + bits.get(DIST_EXTRA[d] as u32) as usize;
+                            assert!(dist <= WINDOW);
+                            for _ in 0..len {
+                                out.push(out[out.len() - dist]);
+                            }
+                        }
+                    }
+                },
+                btype => panic!("block type {btype}"),
+            }
+            if last {
+                break;
+            }
+        }
+        let trailer = &gz[10 + bits.pos.div_ceil(8)..];
+        assert_eq!(trailer.len(), 8);
+        let mut crc = Crc32::new();
+        crc.update(&out);
+        assert_eq!(trailer[..4], crc.sum().to_le_bytes());
+        assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.sum(), 0xcbf4_3926);
+        // Split across the slice-by-8 chunks
+        let mut crc = Crc32::new();
+        crc.update(b"123");
+        crc.update(b"456789");
+        assert_eq!(crc.sum(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut input = Vec::new();
+        let layout = Layout::plain(NumFormat::default());
+        fast_buzz(&layout, 1..=100_000, Stride::ALL, 4096, &mut input).unwrap();
+        // Past a window and a block, with a sync flush in the middle
+        assert!(input.len() > BLOCK + WINDOW);
+        let mut gz = GzEncoder::new(Vec::new()).unwrap();
+        gz.write_all(&input[..BLOCK / 2]).unwrap();
+        gz.flush().unwrap();
+        gz.write_all(&input[BLOCK / 2..]).unwrap();
+        let gz = gz.finish().unwrap();
+        assert!(gunzip(&gz) == input);
+    }
+}
@@ -0,0 +1,301 @@
+use std::io::{self, Write};
+
+use crate::buffer::Buffer;
+use crate::counter::{AsciiCounter, NumFormat, Pad, count_digits};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Number,
+    Fizz,
+    Buzz,
+    FizzBuzz,
+}
+
+impl Class {
+    pub const ALL: [Class; 4] = [Class::Number, Class::Fizz, Class::Buzz, Class::FizzBuzz];
+
+    pub fn of(n: u64) -> Class {
+        match (n.is_multiple_of(3), n.is_multiple_of(5)) {
+            (true, true) => Class::FizzBuzz,
+            (true, false) => Class::Fizz,
+            (false, true) => Class::Buzz,
+            (false, false) => Class::Number,
+        }
+    }
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// The number itself.
+    N,
+    /// Counting lines from 1, like `nl`.
+    LineNo,
+}
+
+/// A number on the line, and how it's spelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub value: Value,
+    pub fmt: NumFormat,
+}
+
+impl Field {
+    pub const fn n(fmt: NumFormat) -> Field {
+        Field {
+            value: Value::N,
+            fmt,
+        }
+    }
+    /// What the field says on the line for `n`, when the first line is `origin`.
+    pub fn eval(self, n: u64, origin: i128) -> u64 {
+        match self.value {
+            Value::N => n,
+            Value::LineNo => (n as i128 - origin).unsigned_abs() as u64 + 1,
+        }
+    }
+    /// Whether the field goes up from one line to the next. Line numbers always do.
+    pub fn rises(self, descending: bool) -> bool {
+        match self.value {
+            Value::N => !descending,
+            Value::LineNo => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Piece {
+    Lit(Vec<u8>),
+    Num(Field),
+}
+
+/// What every kind of line looks like, as a sequence of literals and numbers.
+#[derive(Clone)]
+pub struct Layout {
+    lines: [Vec<Piece>; 4],
+    /// Every distinct field that shows up in `lines`.
+    fields: Vec<Field>,
+}
+
+impl Layout {
+    pub fn new(lines: [Vec<Piece>; 4]) -> Layout {
+        let mut fields = Vec::new();
+        for piece in lines.iter().flatten() {
+            if let Piece::Num(field) = piece
+                && !fields.contains(field)
+            {
+                fields.push(*field);
+            }
+        }
+        Layout { lines, fields }
+    }
+    pub fn plain(fmt: NumFormat) -> Layout {
+        let word = |w: &str| {
+            let w = match fmt.pad {
+                Pad::None => w.to_string(),
+                Pad::Zeros | Pad::Spaces => format!("{w:>0$}", fmt.len(1)),
+            };
+            vec![Piece::Lit(format!("{w}\n").into_bytes())]
+        };
+        Layout::new([
+            vec![Piece::Num(Field::n(fmt)), Piece::Lit(b"\n".to_vec())],
+            word("Fizz"),
+            word("Buzz"),
+            word("FizzBuzz"),
+        ])
+    }
+    /// `{"n":15,"v":"FizzBuzz"}`, `{"n":16,"v":16}`
+    pub fn jsonl() -> Layout {
+        let n = Field::n(NumFormat::default());
+        let word = |w: &str| {
+            vec![
+                Piece::Lit(b"{\"n\":".to_vec()),
+                Piece::Num(n),
+                Piece::Lit(format!(",\"v\":\"{w}\"}}\n").into_bytes()),
+            ]
+        };
+        Layout::new([
+            vec![
+                Piece::Lit(b"{\"n\":".to_vec()),
+                Piece::Num(n),
+                Piece::Lit(b",\"v\":".to_vec()),
+                Piece::Num(n),
+                Piece::Lit(b"}\n".to_vec()),
+            ],
+            word("Fizz"),
+            word("Buzz"),
+            word("FizzBuzz"),
+        ])
+    }
+    /// `n,value` rows, with `sep` between the columns.
+    pub fn delimited(sep: u8) -> Layout {
+        // Every line now has a number in it, so with d digits a cycle looks like
+        // line     | bytes  | sites
+        // 1        | 2d + 2 | 2
+        // 2        | 2d + 2 | 2
+        // Fizz     | d + 6  | 1
+        // 4        | 2d + 2 | 2
+        // Buzz     | d + 6  | 1
+        // Fizz     | d + 6  | 1
+        // 7        | 2d + 2 | 2
+        // 8        | 2d + 2 | 2
+        // Fizz     | d + 6  | 1
+        // Buzz     | d + 6  | 1
+        // 11       | 2d + 2 | 2
+        // Fizz     | d + 6  | 1
+        // 13       | 2d + 2 | 2
+        // 14       | 2d + 2 | 2
+        // FizzBuzz | d + 10 | 1
+        // Total    | 23d + 62 bytes, 23 sites
+        let n = Field::n(NumFormat::default());
+        let word = |w: &str| {
+            let mut lit = vec![sep];
+            lit.extend_from_slice(w.as_bytes());
+            lit.push(b'\n');
+            vec![Piece::Num(n), Piece::Lit(lit)]
+        };
+        Layout::new([
+            vec![
+                Piece::Num(n),
+                Piece::Lit(vec![sep]),
+                Piece::Num(n),
+                Piece::Lit(b"\n".to_vec()),
+            ],
+            word("Fizz"),
+            word("Buzz"),
+            word("FizzBuzz"),
+        ])
+    }
+    pub fn delimited_header(sep: u8) -> Vec<u8> {
+        let mut header = b"n".to_vec();
+        header.push(sep);
+        header.extend_from_slice(b"value\n");
+        header
+    }
+    /// Puts an `nl`-style line number in front of every line, as in `     15\tFizzBuzz`.
+    pub fn numbered(self) -> Layout {
+        let line_no = Field {
+            value: Value::LineNo,
+            fmt: NumFormat {
+                pad: Pad::Spaces,
+                width: 6,
+                sep: None,
+            },
+        };
+        Layout::new(self.lines.map(|line| {
+            let mut numbered = vec![Piece::Num(line_no), Piece::Lit(b"\t".to_vec())];
+            numbered.extend(line);
+            numbered
+        }))
+    }
+    /// The same lines for `-n`, with the counters counting the magnitude.
+    pub fn negated(&self) -> Layout {
+        Layout::new(self.lines.clone().map(|line| {
+            let mut negated: Vec<Piece> = Vec::new();
+            for piece in line {
+                if let Piece::Num(Field {
+                    value: Value::N, ..
+                }) = piece
+                {
+                    match negated.last_mut() {
+                        Some(Piece::Lit(lit)) => lit.push(b'-'),
+                        _ => negated.push(Piece::Lit(b"-".to_vec())),
+                    }
+                }
+                negated.push(piece);
+            }
+            negated
+        }))
+    }
+    /// Leaves out the lines of every class that isn't in `classes`.
+    pub fn only(self, classes: &[Class]) -> Layout {
+        let mut lines = self.lines;
+        for class in Class::ALL {
+            if !classes.contains(&class) {
+                lines[class.index()].clear();
+            }
+        }
+        Layout::new(lines)
+    }
+    pub fn line(&self, class: Class) -> &[Piece] {
+        &self.lines[class.index()]
+    }
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+    /// Whether each field goes up from one line to the next.
+    pub fn rises(&self, descending: bool) -> Vec<bool> {
+        self.fields
+            .iter()
+            .map(|field| field.rises(descending))
+            .collect()
+    }
+    /// A counter per field, starting at the line for `n`.
+    pub fn counters(&self, n: u64, origin: i128) -> Vec<AsciiCounter> {
+        self.fields
+            .iter()
+            .map(|field| AsciiCounter::new(field.eval(n, origin), field.fmt))
+            .collect()
+    }
+    /// How many more lines past the one for `n`, going up or down, before any of the fields change
+    /// width.
+    pub fn segment_len(&self, n: u64, origin: i128, descending: bool) -> u64 {
+        self.fields
+            .iter()
+            .map(|field| {
+                let value = field.eval(n, origin);
+                match field.rises(descending) {
+                    true => field.fmt.segment_end(value) - value,
+                    false => value - field.fmt.segment_start(value),
+                }
+            })
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+    pub fn line_len(&self, n: u64, origin: i128) -> usize {
+        self.lines[Class::of(n).index()]
+            .iter()
+            .map(|piece| match piece {
+                Piece::Lit(bytes) => bytes.len(),
+                Piece::Num(field) => field.fmt.len(count_digits(field.eval(n, origin))),
+            })
+            .sum()
+    }
+    pub fn max_line_len(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|piece| match piece {
+                        Piece::Lit(bytes) => bytes.len(),
+                        Piece::Num(field) => field.fmt.max_len(),
+                    })
+                    .sum()
+            })
+            .max()
+            .unwrap()
+    }
+    /// Writes the line for the counters' current values, recording the offset of the ones digit
+    /// of every number in the `sites` for its field.
+    pub fn render(
+        &self,
+        class: Class,
+        counters: &[AsciiCounter],
+        buf: &mut Buffer,
+        sites: &mut [Vec<usize>],
+    ) -> io::Result<()> {
+        for piece in &self.lines[class.index()] {
+            match piece {
+                Piece::Lit(bytes) => buf.write_all(bytes)?,
+                Piece::Num(field) => {
+                    let i = self.fields.iter().position(|f| f == field).unwrap();
+                    buf.write_all(counters[i].view_ascii())?;
+                    sites[i].push(buf.len() - 1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
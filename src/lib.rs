@@ -0,0 +1,19 @@
+pub mod binary;
+pub mod buffer;
+pub mod compact;
+pub mod counter;
+pub mod direct;
+pub mod gzip;
+pub mod layout;
+pub mod memfd;
+pub mod mmap;
+pub mod parallel;
+pub mod pretty;
+pub mod pwrite;
+pub mod roman;
+pub mod sys;
+pub mod template;
+pub mod uring;
+pub mod vmsplice;
+pub mod words;
+pub mod writev;
@@ -0,0 +1,82 @@
+//! Output to a sealed file in memory, to hand to whoever wants it as a file descriptor.
+//!
+//! A `memfd_create(2)` file is a tmpfs file without a name: it reads, seeks and maps like any
+//! other, and never touches a disk. Once it's filled, it's sealed against writes, growing and
+//! shrinking, so whoever gets it, in this process or a child, can count on it staying as it was
+//! made.
+
+use std::fs::File;
+use std::io;
+use std::ops::RangeInclusive;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::process::{Child, Command, Stdio};
+
+use crate::layout::Layout;
+use crate::parallel::parallel_buzz;
+use crate::sys;
+use crate::template::{Stride, buzz_len};
+
+/// `preamble`, and then the lines of `range`, generated on `threads` threads into a sealed memfd.
+/// The file's position is at the start.
+///
+/// Refuses a range that won't fit in the memory that's available.
+pub fn sealed_buzz(
+    layout: &Layout,
+    preamble: &[u8],
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    capacity: usize,
+    threads: usize,
+) -> io::Result<File> {
+    let len = preamble.len() as u128 + buzz_len(layout, range.clone(), stride)?;
+    let available = sys::available_memory()?;
+    if len > available as u128 {
+        return Err(io::Error::new(
+            io::ErrorKind::OutOfMemory,
+            format!(
+                "the output is {len} bytes, and there are only {available} bytes of memory for it"
+            ),
+        ));
+    }
+    let file = File::from(sys::memfd(c"fizzbuzz")?);
+    file.write_all_at(preamble, 0)?;
+    let offset = preamble.len() as u64;
+    parallel_buzz(layout, range, stride, capacity, threads, &file, offset)?;
+    sys::seal(file.as_raw_fd())?;
+    Ok(file)
+}
+
+/// Starts `command` reading from `file`, from wherever its position is.
+pub fn spawn_with_stdin(command: &mut Command, file: File) -> io::Result<Child> {
+    command.stdin(Stdio::from(file)).spawn()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::fast_buzz;
+    use std::io::Read;
+
+    #[test]
+    fn sealed_file_holds_the_output_for_good() {
+        let layout = Layout::delimited(b',').numbered();
+        let preamble = Layout::delimited_header(b',');
+        let range = -1000..=100_000;
+        let mut file =
+            sealed_buzz(&layout, &preamble, range.clone(), Stride::ALL, 4096, 3).unwrap();
+
+        let mut expected = preamble.clone();
+        fast_buzz(&layout, range, Stride::ALL, 4096, &mut expected).unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert!(contents == expected);
+
+        let seals = sys::seals(file.as_raw_fd()).unwrap();
+        let wanted = sys::F_SEAL_WRITE | sys::F_SEAL_GROW | sys::F_SEAL_SHRINK;
+        assert_eq!(seals & wanted, wanted);
+        assert!(file.write_all_at(b"x", 0).is_err());
+        assert!(file.set_len(0).is_err());
+        assert!(file.set_len(expected.len() as u64 + 1).is_err());
+    }
+}
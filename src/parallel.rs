@@ -0,0 +1,226 @@
+//! Generating a file on every core.
+//!
+//! Every line's length follows from the widths of its numbers, so where each chunk of lines ends
+//! up in the file is known before any of it's generated. Each thread takes the next chunk, runs it
+//! through the template engine, and `pwrite(2)`s the buffers straight to their place.
+
+use std::fs::File;
+use std::io;
+use std::ops::RangeInclusive;
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::buffer::Buffer;
+use crate::layout::Layout;
+use crate::template::{Output, Stride, fast_segment, for_each_segment, segment_bytes};
+
+/// Output per chunk, roughly. Big enough that filling the first buffer of a chunk is a rounding
+/// error, small enough that the threads finish close together.
+#[cfg(not(test))]
+const CHUNK_BYTES: u128 = 64 * 1024 * 1024;
+/// Small enough for the tests to cut every segment into plenty of chunks.
+#[cfg(test)]
+const CHUNK_BYTES: u128 = 64 * 1024;
+
+/// A width segment, cut into chunks of `chunk_lines` lines.
+struct Segment {
+    layout: Layout,
+    first: u64,
+    lines: u128,
+    origin: i128,
+    stride: Stride,
+    /// Where the segment starts in the file.
+    offset: u128,
+    bytes: u128,
+    chunk_lines: u128,
+    chunk_bytes: u128,
+    /// Chunks in all the segments before this one.
+    chunks_before: u64,
+}
+
+/// Lines of one segment, and where they go in the file.
+pub(crate) struct Chunk<'a> {
+    pub(crate) layout: &'a Layout,
+    pub(crate) first: u64,
+    pub(crate) lines: u128,
+    pub(crate) origin: i128,
+    pub(crate) stride: Stride,
+    pub(crate) offset: u64,
+    pub(crate) bytes: u64,
+}
+
+/// Where every chunk of a range goes in the file, worked out before any of it's generated.
+pub(crate) struct Plan {
+    segments: Vec<Segment>,
+    chunks: u64,
+    /// Where the last chunk ends.
+    pub(crate) end: u64,
+}
+
+impl Plan {
+    /// The lines of `range`, from `offset` on in the file.
+    pub(crate) fn new(
+        layout: &Layout,
+        range: RangeInclusive<i128>,
+        stride: Stride,
+        offset: u64,
+    ) -> io::Result<Plan> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut end = offset as u128;
+        let mut chunks = 0;
+        for_each_segment(
+            layout,
+            range,
+            stride,
+            |layout, first, lines, origin, stride| {
+                // Nothing to write, and no chunks to write it in
+                let bytes = segment_bytes(layout, first, lines, origin, stride);
+                if bytes == 0 {
+                    return Ok(());
+                }
+                // Whatever the segment writes, its first period does
+                let period = stride.period() as u128;
+                let period_bytes = segment_bytes(layout, first, period, origin, stride);
+                let chunk_lines = std::cmp::max(CHUNK_BYTES / period_bytes, 1) * period;
+                segments.push(Segment {
+                    layout: layout.clone(),
+                    first,
+                    lines,
+                    origin,
+                    stride,
+                    offset: end,
+                    bytes,
+                    chunk_lines,
+                    chunk_bytes: chunk_lines / period * period_bytes,
+                    chunks_before: chunks,
+                });
+                end += bytes;
+                chunks += lines.div_ceil(chunk_lines) as u64;
+                Ok(())
+            },
+        )?;
+        // pwrite(2) and mmap(2) take signed offsets
+        if end > i64::MAX as u128 {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("{end} bytes is too much for one file"),
+            ));
+        }
+        Ok(Plan {
+            segments,
+            chunks,
+            end: end as u64,
+        })
+    }
+
+    fn chunk(&self, chunk: u64) -> Chunk<'_> {
+        let i = self.segments.partition_point(|s| s.chunks_before <= chunk) - 1;
+        let segment = &self.segments[i];
+        let j = (chunk - segment.chunks_before) as u128;
+        let skipped = j * segment.chunk_lines;
+        let done = j * segment.chunk_bytes;
+        Chunk {
+            layout: &segment.layout,
+            first: segment.stride.nth(segment.first, skipped as u64),
+            lines: std::cmp::min(segment.chunk_lines, segment.lines - skipped),
+            origin: segment.origin,
+            stride: segment.stride,
+            offset: (segment.offset + done) as u64,
+            bytes: std::cmp::min(segment.chunk_bytes, segment.bytes - done) as u64,
+        }
+    }
+
+    /// Hands every chunk to `work` on `threads` threads, each with a buffer of `capacity` bytes,
+    /// until they're all done or one fails.
+    pub(crate) fn run(
+        &self,
+        threads: usize,
+        capacity: usize,
+        work: impl Fn(&Chunk, &mut Buffer) -> io::Result<()> + Sync,
+    ) -> io::Result<()> {
+        let next = AtomicU64::new(0);
+        let failed = AtomicBool::new(false);
+        let thread = || -> io::Result<()> {
+            let mut buf = Buffer::with_capacity(capacity);
+            while !failed.load(Ordering::Relaxed) {
+                let chunk = next.fetch_add(1, Ordering::Relaxed);
+                if chunk >= self.chunks {
+                    break;
+                }
+                if let Err(e) = work(&self.chunk(chunk), &mut buf) {
+                    failed.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+            Ok(())
+        };
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads).map(|_| scope.spawn(thread)).collect();
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().unwrap())
+        })
+    }
+}
+
+/// Writes to a fixed place in a file, rather than its current position.
+struct At<'a> {
+    file: &'a File,
+    offset: u64,
+    buf: &'a mut Buffer,
+}
+
+impl Output for At<'_> {
+    fn ring_len(&self) -> usize {
+        1
+    }
+    fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+    fn slot(&mut self, _: usize) -> io::Result<&mut Buffer> {
+        Ok(self.buf)
+    }
+    fn send_slot(&mut self, _: usize) -> io::Result<()> {
+        self.file.write_all_at(self.buf.view(), self.offset)?;
+        self.offset += self.buf.len() as u64;
+        Ok(())
+    }
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all_at(bytes, self.offset)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Like [`fast_buzz`](crate::template::fast_buzz), but writing to `file` from `offset` on, on
+/// `threads` threads. Leaves the file's position alone.
+pub fn parallel_buzz(
+    layout: &Layout,
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    capacity: usize,
+    threads: usize,
+    file: &File,
+    offset: u64,
+) -> io::Result<()> {
+    let plan = Plan::new(layout, range, stride, offset)?;
+    plan.run(threads, capacity, |chunk, buf| {
+        let mut out = At {
+            file,
+            offset: chunk.offset,
+            buf,
+        };
+        let Chunk {
+            layout,
+            first,
+            lines,
+            origin,
+            stride,
+            ..
+        } = *chunk;
+        fast_segment(layout, first, lines, origin, stride, &mut out)
+    })
+}
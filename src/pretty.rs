@@ -0,0 +1,113 @@
+//! Human-friendly output for terminals: colours, columns, and a pace you can actually read.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::counter::{AsciiCounter, NumFormat, count_digits};
+use crate::layout::Class;
+
+const FIZZ: &[u8] = b"\x1b[32m";
+const BUZZ: &[u8] = b"\x1b[34m";
+const FIZZ_BUZZ: &[u8] = b"\x1b[1;35m";
+const RESET: &[u8] = b"\x1b[0m";
+/// Spaces between columns.
+const GAP: usize = 2;
+
+pub struct PrettyOptions {
+    pub color: bool,
+    /// Fill the rows up to this many columns of terminal, rather than one value per line.
+    pub width: Option<usize>,
+    /// Lines per second, which are rows of several values with `width`, or as fast as possible.
+    pub rate: Option<u32>,
+}
+
+pub fn pretty_buzz<W: Write>(
+    range: RangeInclusive<u64>,
+    opts: &PrettyOptions,
+    out: &mut W,
+) -> io::Result<()> {
+    if range.is_empty() {
+        return Ok(());
+    }
+    let (mut n, end) = range.into_inner();
+    let cell = std::cmp::max(count_digits(end), b"FizzBuzz".len());
+    let per_row = match opts.width {
+        Some(width) => std::cmp::max(1, (width + GAP) / (cell + GAP)),
+        None => 1,
+    };
+
+    let started = Instant::now();
+    let mut rows = 0;
+    let mut column = 0;
+    let mut row = Vec::new();
+    let mut counter = AsciiCounter::new(n, NumFormat::default());
+    loop {
+        let (color, text) = match Class::of(n) {
+            Class::FizzBuzz => (FIZZ_BUZZ, &b"FizzBuzz"[..]),
+            Class::Fizz => (FIZZ, &b"Fizz"[..]),
+            Class::Buzz => (BUZZ, &b"Buzz"[..]),
+            Class::Number => (&b""[..], counter.view_ascii()),
+        };
+        if opts.color && !color.is_empty() {
+            row.extend_from_slice(color);
+            row.extend_from_slice(text);
+            row.extend_from_slice(RESET);
+        } else {
+            row.extend_from_slice(text);
+        }
+
+        column += 1;
+        if n == end || column == per_row {
+            column = 0;
+            row.push(b'\n');
+            out.write_all(&row)?;
+            row.clear();
+            rows += 1;
+            if let Some(rate) = opts.rate {
+                out.flush()?;
+                let due = started + Duration::from_secs_f64(rows as f64 / rate as f64);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        } else {
+            row.resize(row.len() + cell - text.len() + GAP, b' ');
+        }
+
+        if n == end {
+            return Ok(());
+        }
+        n += 1;
+        counter.bump(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_only_the_words_in_padded_cells() {
+        let opts = PrettyOptions {
+            color: true,
+            width: Some(40),
+            rate: None,
+        };
+        let mut out = Vec::new();
+        pretty_buzz(1..=15, &opts, &mut out).unwrap();
+
+        // Cells of 8 and 2 spaces between, so four to a row of 40
+        let n = |n: u64| format!("{n:<10}");
+        let fizz = "\x1b[32mFizz\x1b[0m      ";
+        let buzz = "\x1b[34mBuzz\x1b[0m      ";
+        let expected = [
+            format!("{}{}{fizz}4\n", n(1), n(2)),
+            format!("{buzz}{fizz}{}8\n", n(7)),
+            format!("{fizz}{buzz}{}\x1b[32mFizz\x1b[0m\n", n(11)),
+            format!("{}{}\x1b[1;35mFizzBuzz\x1b[0m\n", n(13), n(14)),
+        ]
+        .concat();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
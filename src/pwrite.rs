@@ -0,0 +1,150 @@
+//! Output to a regular file in large, aligned blocks.
+//!
+//! Writes that start and end on block boundaries never have the kernel read in a partly
+//! overwritten block first. Only the first block can't be aligned, when the file didn't start out
+//! empty, and it's cut short to line up the rest.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+
+use crate::buffer::Buffer;
+
+/// A file, and how to write a block to it.
+pub trait WriteAt {
+    fn file(&self) -> &File;
+    fn write_all_at(&mut self, bytes: &[u8], offset: u64) -> io::Result<()>;
+}
+
+impl WriteAt for File {
+    fn file(&self) -> &File {
+        self
+    }
+    fn write_all_at(&mut self, bytes: &[u8], offset: u64) -> io::Result<()> {
+        FileExt::write_all_at(self, bytes, offset)
+    }
+}
+
+pub type Pwrite = Blocks<File>;
+
+/// Buffers up whatever's written, and hands it to `out` a block at a time.
+pub struct Blocks<F: WriteAt> {
+    out: F,
+    /// Where `buf` goes in the file.
+    offset: u64,
+    buf: Buffer,
+}
+
+impl Pwrite {
+    /// Starts at the file's current position. Blocks are `capacity` bytes, which should be a
+    /// multiple of the file system's block size.
+    pub fn new(file: File, capacity: usize) -> io::Result<Pwrite> {
+        Blocks::with(file, capacity)
+    }
+}
+
+impl<F: WriteAt> Blocks<F> {
+    /// Starts at the file's current position, with blocks of `capacity` bytes.
+    pub(crate) fn with(out: F, capacity: usize) -> io::Result<Blocks<F>> {
+        let offset = out.file().stream_position()?;
+        Ok(Blocks {
+            out,
+            offset,
+            buf: Buffer::with_capacity(capacity),
+        })
+    }
+
+    /// Room left in `buf` before the next boundary.
+    fn room(&self) -> usize {
+        let capacity = self.buf.capacity() as u64;
+        (capacity - self.offset % capacity) as usize - self.buf.len()
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        self.out.write_all_at(self.buf.view(), self.offset)?;
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<F: WriteAt> Write for Blocks<F> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let n = std::cmp::min(bytes.len(), self.room());
+        self.buf.write_all(&bytes[..n])?;
+        if self.room() == 0 {
+            self.write_out()?;
+        }
+        Ok(n)
+    }
+    /// Writes out what's buffered, and leaves the file's position after it.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_out()?;
+        }
+        self.out.file().seek(SeekFrom::Start(self.offset))?;
+        Ok(())
+    }
+}
+
+impl<F: WriteAt> Drop for Blocks<F> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes through to the file, noting down every `(offset, len)`.
+    struct Recording {
+        file: File,
+        writes: Vec<(u64, usize)>,
+    }
+
+    impl WriteAt for Recording {
+        fn file(&self) -> &File {
+            &self.file
+        }
+        fn write_all_at(&mut self, bytes: &[u8], offset: u64) -> io::Result<()> {
+            self.writes.push((offset, bytes.len()));
+            self.file.write_all_at(bytes, offset)
+        }
+    }
+
+    #[test]
+    fn blocks_line_up_after_the_first() {
+        let path = std::env::temp_dir().join(format!("firehose-pwrite-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[b'#'; 100]).unwrap();
+        let writes = {
+            let recording = Recording {
+                file: file.try_clone().unwrap(),
+                writes: Vec::new(),
+            };
+            let mut out = Blocks::with(recording, 4096).unwrap();
+            for i in 0..5000_u32 {
+                writeln!(out, "{i}").unwrap();
+            }
+            out.flush().unwrap();
+            std::mem::take(&mut out.out.writes)
+        };
+
+        // A short first block up to the boundary, whole ones after it, and whatever's left
+        assert_eq!(writes[0], (100, 4096 - 100));
+        for &(offset, len) in &writes[1..writes.len() - 1] {
+            assert_eq!((offset % 4096, len), (0, 4096));
+        }
+        let mut expected = vec![b'#'; 100];
+        for i in 0..5000_u32 {
+            writeln!(expected, "{i}").unwrap();
+        }
+        let &(offset, len) = writes.last().unwrap();
+        assert_eq!(offset % 4096, 0);
+        assert_eq!(offset + len as u64, expected.len() as u64);
+        assert!(std::fs::read(&path).unwrap() == expected);
+        assert_eq!(file.stream_position().unwrap(), expected.len() as u64);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+//! Roman numerals.
+//!
+//! Past `MMMCMXCIX` (3999) we use parenthesised thousands: `(X)` is ten thousand, so `12345` is
+//! `(XII)CCCXLV`. These nest, so `((IV))` is four million, which is enough to get to `u64::MAX`.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::buffer::Buffer;
+use crate::layout::Class;
+
+const NUMERALS: [(u16, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+/// Everything below this is plain numerals.
+const PLAIN_LIMIT: u64 = 4000;
+/// Six levels of parentheses around at most `MMMDCCCLXXXVIII` each.
+const MAX_LINE_LEN: usize = 256;
+
+fn push_plain(mut n: u16, s: &mut String) {
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            s.push_str(numeral);
+            n -= value;
+        }
+    }
+}
+
+pub fn to_roman(n: u64) -> String {
+    let mut s = String::new();
+    if n < PLAIN_LIMIT {
+        push_plain(n as u16, &mut s);
+    } else {
+        s.push('(');
+        s.push_str(&to_roman(n / 1000));
+        s.push(')');
+        push_plain((n % 1000) as u16, &mut s);
+    }
+    s
+}
+
+pub fn from_roman(s: &[u8]) -> Option<u64> {
+    let (thousands, mut rest) = match s.first() {
+        Some(b'(') => {
+            let mut depth = 0;
+            let close = s.iter().position(|&c| {
+                match c {
+                    b'(' => depth += 1,
+                    b')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })?;
+            (from_roman(&s[1..close])?.checked_mul(1000)?, &s[close + 1..])
+        }
+        _ => (0, s),
+    };
+    let mut n = 0;
+    for (value, numeral) in NUMERALS {
+        while let Some(tail) = rest.strip_prefix(numeral.as_bytes()) {
+            n += value as u64;
+            rest = tail;
+        }
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    thousands.checked_add(n)
+}
+
+/// Like `WordCounter`: plain numerals come from a table, and the parenthesised thousands are
+/// cached until the next carry.
+pub struct RomanCounter {
+    table: Vec<Vec<u8>>,
+    n: u64,
+    prefix: Vec<u8>,
+}
+
+impl RomanCounter {
+    pub fn new(start: u64) -> RomanCounter {
+        let table: Vec<Vec<u8>> = (0..PLAIN_LIMIT).map(|n| to_roman(n).into_bytes()).collect();
+        let mut counter = RomanCounter {
+            table,
+            n: start,
+            prefix: Vec::new(),
+        };
+        counter.update_prefix();
+        counter
+    }
+    fn update_prefix(&mut self) {
+        self.prefix.clear();
+        if self.n >= PLAIN_LIMIT {
+            self.prefix.push(b'(');
+            self.prefix.extend_from_slice(to_roman(self.n / 1000).as_bytes());
+            self.prefix.push(b')');
+        }
+    }
+    pub fn bump(&mut self) {
+        self.n += 1;
+        if self.n.is_multiple_of(1000) {
+            self.update_prefix();
+        }
+    }
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.n < PLAIN_LIMIT {
+            out.write_all(&self.table[self.n as usize])
+        } else {
+            out.write_all(&self.prefix)?;
+            out.write_all(&self.table[(self.n % 1000) as usize])
+        }
+    }
+}
+
+/// FizzBuzz with Roman numerals: `I`, `II`, `Fizz`, `IV`, `Buzz`, ...
+pub fn roman_buzz<W: Write>(range: RangeInclusive<u64>, out: &mut W) -> io::Result<()> {
+    if range.is_empty() {
+        return Ok(());
+    }
+    let (mut n, end) = range.into_inner();
+    let mut buf = Buffer::new();
+    let mut counter = RomanCounter::new(n);
+    loop {
+        if buf.spare_capacity() < MAX_LINE_LEN {
+            out.write_all(buf.view())?;
+            buf.clear();
+        }
+        match Class::of(n) {
+            Class::FizzBuzz => buf.write_all(b"FizzBuzz\n")?,
+            Class::Fizz => buf.write_all(b"Fizz\n")?,
+            Class::Buzz => buf.write_all(b"Buzz\n")?,
+            Class::Number => {
+                counter.write_to(&mut buf)?;
+                buf.write_all(b"\n")?;
+            }
+        }
+        if n == end {
+            break;
+        }
+        n += 1;
+        counter.bump();
+    }
+    out.write_all(buf.view())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_matches_integers() {
+        let counter = RomanCounter::new(0);
+        for (n, numeral) in counter.table.iter().enumerate() {
+            assert_eq!(from_roman(numeral), Some(n as u64), "{n}");
+        }
+        assert_eq!(to_roman(1994), "MCMXCIV");
+        assert_eq!(to_roman(3999), "MMMCMXCIX");
+        assert_eq!(to_roman(12345), "(XII)CCCXLV");
+        assert_eq!(to_roman(4_000_000), "((IV))");
+    }
+
+    /// Bumps a counter from `start` through `lines` numbers, checking every one.
+    fn check_counter(start: u64, lines: u64) {
+        let mut counter = RomanCounter::new(start);
+        for n in start..=start + (lines - 1) {
+            let mut out = Vec::new();
+            counter.write_to(&mut out).unwrap();
+            assert_eq!(out, to_roman(n).as_bytes(), "{n}");
+            assert_eq!(from_roman(&out), Some(n));
+            if n < start + (lines - 1) {
+                counter.bump();
+            }
+        }
+    }
+
+    #[test]
+    fn counter_matches_to_roman_across_prefix_changes() {
+        // Into the parentheses, and on through x999 to (x+1)000 at every depth
+        check_counter(1, 5_000);
+        check_counter(4_990, 20);
+        check_counter(9_990, 20);
+        check_counter(999_990, 20);
+        check_counter(3_999_990, 20);
+        check_counter(3_999_999_990, 20);
+        check_counter(u64::MAX - 2_000, 2_001);
+    }
+}
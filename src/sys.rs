@@ -0,0 +1,328 @@
+//! The few bits of libc we need, declared by hand to stay std-only.
+//!
+//! std already links against libc, so these resolve without any extra crates.
+
+use std::ffi::{CStr, c_char};
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+
+#[repr(C)]
+#[derive(Default)]
+struct Winsize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Statvfs {
+    f_bsize: u64,
+    f_frsize: u64,
+    f_blocks: u64,
+    f_bfree: u64,
+    f_bavail: u64,
+    f_files: u64,
+    f_ffree: u64,
+    f_favail: u64,
+    f_fsid: u64,
+    f_flag: u64,
+    f_namemax: u64,
+    spare: [i32; 8],
+}
+
+#[repr(C)]
+pub struct Iovec {
+    pub base: *const u8,
+    pub len: usize,
+}
+
+const TIOCGWINSZ: u64 = 0x5413;
+const FIONREAD: u64 = 0x541b;
+const FALLOC_FL_KEEP_SIZE: i32 = 1;
+const EOPNOTSUPP: i32 = 95;
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+#[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+const O_DIRECT: i32 = 0o200000;
+#[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+const O_DIRECT: i32 = 0o400000;
+#[cfg(not(any(
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "powerpc",
+    target_arch = "powerpc64"
+)))]
+const O_DIRECT: i32 = 0o40000;
+const F_SETPIPE_SZ: i32 = 1031;
+const F_ADD_SEALS: i32 = 1033;
+const F_GET_SEALS: i32 = 1034;
+pub const F_SEAL_SEAL: i32 = 0x1;
+pub const F_SEAL_SHRINK: i32 = 0x2;
+pub const F_SEAL_GROW: i32 = 0x4;
+pub const F_SEAL_WRITE: i32 = 0x8;
+const MFD_CLOEXEC: u32 = 0x1;
+const MFD_ALLOW_SEALING: u32 = 0x2;
+const F_GETPIPE_SZ: i32 = 1032;
+const SYS_IO_URING_SETUP: i64 = 425;
+const SYS_IO_URING_ENTER: i64 = 426;
+const SYS_IO_URING_REGISTER: i64 = 427;
+const SOL_SOCKET: i32 = 1;
+const SO_SNDBUF: i32 = 7;
+const PROT_READ_WRITE: i32 = 0x1 | 0x2;
+const MAP_SHARED: i32 = 0x01;
+const MAP_POPULATE: i32 = 0x8000;
+const _SC_PAGESIZE: i32 = 30;
+/// Expect the pages in order, so read ahead and drop them early.
+pub const MADV_SEQUENTIAL: i32 = 2;
+/// Fault every page in for writing now, rather than one at a time on first touch. Since Linux
+/// 5.14.
+pub const MADV_POPULATE_WRITE: i32 = 23;
+
+unsafe extern "C" {
+    fn ioctl(fd: RawFd, request: u64, ...) -> i32;
+    fn fcntl(fd: RawFd, cmd: i32, ...) -> i32;
+    fn vmsplice(fd: RawFd, iov: *const Iovec, nr_segs: usize, flags: u32) -> isize;
+    fn setsockopt(fd: RawFd, level: i32, name: i32, value: *const i32, len: u32) -> i32;
+    fn getsockopt(fd: RawFd, level: i32, name: i32, value: *mut i32, len: *mut u32) -> i32;
+    fn syscall(number: i64, ...) -> i64;
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: RawFd, offset: i64) -> *mut u8;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+    fn madvise(addr: *mut u8, len: usize, advice: i32) -> i32;
+    fn fallocate(fd: RawFd, mode: i32, offset: i64, len: i64) -> i32;
+    fn fstatvfs(fd: RawFd, buf: *mut Statvfs) -> i32;
+    fn memfd_create(name: *const c_char, flags: u32) -> i32;
+    fn sysconf(name: i32) -> i64;
+}
+
+/// Turns a libc-style `-1` return into the `errno` error.
+fn check(ret: i64) -> io::Result<i64> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        ret => Ok(ret),
+    }
+}
+
+/// Columns of the terminal behind `fd`.
+pub fn terminal_width(fd: RawFd) -> io::Result<usize> {
+    let mut size = Winsize::default();
+    check(unsafe { ioctl(fd, TIOCGWINSZ, &mut size as *mut Winsize) } as i64)?;
+    Ok(size.ws_col as usize)
+}
+
+/// Capacity of the pipe behind `fd`, and an error if it isn't one.
+pub fn pipe_size(fd: RawFd) -> io::Result<usize> {
+    Ok(check(unsafe { fcntl(fd, F_GETPIPE_SZ) } as i64)? as usize)
+}
+
+/// Asks for the pipe behind `fd` to hold at least `size` bytes, returning what it holds now.
+pub fn set_pipe_size(fd: RawFd, size: usize) -> io::Result<usize> {
+    let size = i32::try_from(size).map_err(io::Error::other)?;
+    Ok(check(unsafe { fcntl(fd, F_SETPIPE_SZ, size) } as i64)? as usize)
+}
+
+/// The most [`set_pipe_size`] can ask for without `CAP_SYS_RESOURCE`.
+pub fn pipe_max_size() -> io::Result<usize> {
+    std::fs::read_to_string("/proc/sys/fs/pipe-max-size")?
+        .trim()
+        .parse()
+        .map_err(io::Error::other)
+}
+
+/// Bytes sitting in the pipe behind `fd`, waiting to be read.
+pub fn pipe_len(fd: RawFd) -> io::Result<usize> {
+    let mut len: i32 = 0;
+    check(unsafe { ioctl(fd, FIONREAD, &mut len as *mut i32) } as i64)?;
+    Ok(len as usize)
+}
+
+/// Turns `O_DIRECT` on or off for the file behind `fd`. Fails if its file system can't do it.
+pub fn set_direct(fd: RawFd, direct: bool) -> io::Result<()> {
+    let flags = check(unsafe { fcntl(fd, F_GETFL) } as i64)? as i32;
+    let flags = match direct {
+        true => flags | O_DIRECT,
+        false => flags & !O_DIRECT,
+    };
+    check(unsafe { fcntl(fd, F_SETFL, flags) } as i64)?;
+    Ok(())
+}
+
+/// Bytes free for us on the file system `fd` is on, leaving out any reserved for root.
+pub fn free_space(fd: RawFd) -> io::Result<u64> {
+    let mut stat = Statvfs::default();
+    check(unsafe { fstatvfs(fd, &mut stat) } as i64)?;
+    Ok(stat.f_bavail * stat.f_frsize)
+}
+
+/// Has the file system set aside `len` bytes from `offset` in the file behind `fd`, without
+/// changing its size. Returns whether it could, as not every file system can. `len` can't be 0.
+pub fn preallocate(fd: RawFd, offset: u64, len: u64) -> io::Result<bool> {
+    let offset = i64::try_from(offset).map_err(io::Error::other)?;
+    let len = i64::try_from(len).map_err(io::Error::other)?;
+    match check(unsafe { fallocate(fd, FALLOC_FL_KEEP_SIZE, offset, len) } as i64) {
+        Ok(_) => Ok(true),
+        Err(e) if e.raw_os_error() == Some(EOPNOTSUPP) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Memory that could be had without swapping, going by `/proc/meminfo`.
+pub fn available_memory() -> io::Result<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+    let kib = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|rest| rest.trim().strip_suffix("kB")?.trim().parse::<u64>().ok())
+        .ok_or_else(|| io::Error::other("no MemAvailable in /proc/meminfo"))?;
+    Ok(kib * 1024)
+}
+
+/// An anonymous file in memory, that can be [`seal`]ed.
+pub fn memfd(name: &CStr) -> io::Result<OwnedFd> {
+    let fd = check(unsafe { memfd_create(name.as_ptr(), MFD_CLOEXEC | MFD_ALLOW_SEALING) } as i64)?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// Seals the [`memfd`] behind `fd` against writes, changes of size, and any more seals. Fails
+/// while it has shared writable mappings.
+pub fn seal(fd: RawFd) -> io::Result<()> {
+    let seals = F_SEAL_WRITE | F_SEAL_GROW | F_SEAL_SHRINK | F_SEAL_SEAL;
+    check(unsafe { fcntl(fd, F_ADD_SEALS, seals) } as i64)?;
+    Ok(())
+}
+
+/// The `F_SEAL_*` seals on the [`memfd`] behind `fd`.
+pub fn seals(fd: RawFd) -> io::Result<i32> {
+    Ok(check(unsafe { fcntl(fd, F_GET_SEALS) } as i64)? as i32)
+}
+
+/// Asks for a send buffer of `size` bytes on the socket behind `fd`, returning what it got.
+///
+/// The kernel caps the request at `net.core.wmem_max`, and then doubles it to leave room for its
+/// own bookkeeping, so only about half of what's returned is for data.
+pub fn set_send_buffer(fd: RawFd, size: usize) -> io::Result<usize> {
+    let size = i32::try_from(size).map_err(io::Error::other)?;
+    check(unsafe { setsockopt(fd, SOL_SOCKET, SO_SNDBUF, &size, 4) } as i64)?;
+    let mut got: i32 = 0;
+    let mut len: u32 = 4;
+    check(unsafe { getsockopt(fd, SOL_SOCKET, SO_SNDBUF, &mut got, &mut len) } as i64)?;
+    Ok(got as usize)
+}
+
+/// Maps `bytes` into the pipe behind `fd` rather than copying them, returning how many made it.
+///
+/// # Safety
+///
+/// The pipe reads straight from `bytes` until the reader gets to them, so they mustn't change or
+/// go away before then.
+pub unsafe fn splice_to_pipe(fd: RawFd, bytes: &[u8]) -> io::Result<usize> {
+    let iov = Iovec {
+        base: bytes.as_ptr(),
+        len: bytes.len(),
+    };
+    // No SPLICE_F_GIFT: we do write to the pages again, once the reader's done with them
+    Ok(check(unsafe { vmsplice(fd, &iov, 1, 0) } as i64)? as usize)
+}
+
+/// `io_uring_setup(2)`, filling in `params`.
+///
+/// # Safety
+///
+/// `params` has to point at a `struct io_uring_params`.
+pub unsafe fn io_uring_setup<P>(entries: u32, params: *mut P) -> io::Result<OwnedFd> {
+    let fd = check(unsafe { syscall(SYS_IO_URING_SETUP, entries as i64, params) })?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// `io_uring_enter(2)`, without a signal mask.
+pub fn io_uring_enter(fd: RawFd, to_submit: u32, min_complete: u32, flags: u32) -> io::Result<u32> {
+    let ret = unsafe {
+        syscall(
+            SYS_IO_URING_ENTER,
+            fd as i64,
+            to_submit as i64,
+            min_complete as i64,
+            flags as i64,
+            std::ptr::null::<u8>(),
+            0_i64,
+        )
+    };
+    Ok(check(ret)? as u32)
+}
+
+/// `io_uring_register(2)`.
+///
+/// # Safety
+///
+/// `arg` has to point at whatever `opcode` expects, `nr_args` of them.
+pub unsafe fn io_uring_register<A>(
+    fd: RawFd,
+    opcode: u32,
+    arg: *const A,
+    nr_args: u32,
+) -> io::Result<()> {
+    check(unsafe {
+        syscall(
+            SYS_IO_URING_REGISTER,
+            fd as i64,
+            opcode as i64,
+            arg,
+            nr_args as i64,
+        )
+    })?;
+    Ok(())
+}
+
+/// What mappings are made of.
+pub fn page_size() -> usize {
+    unsafe { sysconf(_SC_PAGESIZE) as usize }
+}
+
+/// A shared, read-write mapping of `fd`, unmapped on drop.
+pub struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    /// With every page faulted in up front. `offset` has to be a multiple of the page size.
+    pub fn new(fd: RawFd, len: usize, offset: i64) -> io::Result<Mapping> {
+        Self::map(fd, len, offset, MAP_SHARED | MAP_POPULATE)
+    }
+    /// With the pages faulted in as they're touched, or as [`Mapping::advise`]d.
+    pub fn lazy(fd: RawFd, len: usize, offset: i64) -> io::Result<Mapping> {
+        Self::map(fd, len, offset, MAP_SHARED)
+    }
+    fn map(fd: RawFd, len: usize, offset: i64, flags: i32) -> io::Result<Mapping> {
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ_WRITE,
+                flags,
+                fd,
+                offset,
+            )
+        };
+        check(ptr as i64)?;
+        Ok(Mapping { ptr, len })
+    }
+    pub fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+    /// `madvise(2)` for the whole mapping.
+    pub fn advise(&self, advice: i32) -> io::Result<()> {
+        check(unsafe { madvise(self.ptr, self.len, advice) } as i64)?;
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr, self.len) };
+    }
+}
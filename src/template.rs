@@ -0,0 +1,877 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::buffer::{
+    Buffer, ripple_borrow_sub_ascii, ripple_borrow_sub_ascii_grouped, ripple_carry_add_ascii,
+    ripple_carry_add_ascii_grouped,
+};
+use crate::counter::{AsciiCounter, Pad};
+use crate::layout::{Class, Field, Layout, Value};
+
+/// Which lines of the sequence get written: every `step`th one, starting `offset` lines in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stride {
+    pub step: u64,
+    pub offset: u64,
+    /// Counting down from the end of the range, rather than up from the start.
+    pub descending: bool,
+}
+
+impl Stride {
+    pub const ALL: Stride = Stride {
+        step: 1,
+        offset: 0,
+        descending: false,
+    };
+
+    /// The `i`th line from `n`.
+    pub(crate) const fn nth(self, n: u64, i: u64) -> u64 {
+        match self.descending {
+            true => n.wrapping_sub(i.wrapping_mul(self.step)),
+            false => n.wrapping_add(i.wrapping_mul(self.step)),
+        }
+    }
+
+    /// Lines until the classes come round again.
+    pub const fn period(self) -> u64 {
+        let mut gcd = 15;
+        let mut b = self.step % 15;
+        while b > 0 {
+            (gcd, b) = (b, gcd % b);
+        }
+        15 / gcd
+    }
+}
+
+/// How far one buffer of lines moves the numbers on.
+pub(crate) struct Batch {
+    pub(crate) lines: u64,
+    /// `(exp, digit)` for every nonzero digit of `lines * ring * step`, lowest first.
+    digits: Vec<(usize, u8)>,
+}
+
+/// Picks the number of lines per buffer of `capacity` bytes, as a whole number of periods.
+///
+/// Moving a buffer on to its next batch, `ring` batches ahead, is one digit add per nonzero digit
+/// of the difference, per number, so we go for the most bytes per nonzero digit. With a step of 1
+/// and a single buffer that's always a single digit, like `900 * 10^suffix_digits`.
+pub(crate) fn find_batch(
+    bytes_per_period: usize,
+    capacity: usize,
+    stride: Stride,
+    ring: usize,
+) -> Option<Batch> {
+    let period = stride.period();
+    let max_periods = (capacity / bytes_per_period) as u64;
+    let mut best: Option<(u64, u32)> = None;
+    for periods in 1..=max_periods {
+        let Some(delta) = (periods * period * ring as u64).checked_mul(stride.step) else {
+            break;
+        };
+        let nonzero = nonzero_digits(delta).count() as u32;
+        // periods / nonzero >= best_periods / best_nonzero
+        if best.is_none_or(|(p, z)| periods * z as u64 >= p * nonzero as u64) {
+            best = Some((periods, nonzero));
+        }
+    }
+    let (periods, _) = best?;
+    let lines = periods * period;
+    Some(Batch {
+        lines,
+        digits: nonzero_digits(lines * ring as u64 * stride.step).collect(),
+    })
+}
+
+/// `(exp, digit)`, lowest first.
+fn nonzero_digits(n: u64) -> impl Iterator<Item = (usize, u8)> {
+    std::iter::successors(Some(n), |n| Some(n / 10))
+        .take_while(|&n| n > 0)
+        .map(|n| (n % 10) as u8)
+        .enumerate()
+        .filter(|&(_, digit)| digit > 0)
+}
+
+pub(crate) fn bytes_per_period(layout: &Layout, start: u64, origin: i128, stride: Stride) -> usize {
+    (0..stride.period())
+        .map(|i| layout.line_len(stride.nth(start, i), origin))
+        .sum()
+}
+
+/// Bytes in the `lines` lines from the one for `first`, all in one segment.
+pub(crate) fn segment_bytes(
+    layout: &Layout,
+    first: u64,
+    lines: u128,
+    origin: i128,
+    stride: Stride,
+) -> u128 {
+    let period = stride.period() as u128;
+    let rest: u128 = (0..(lines % period) as u64)
+        .map(|i| layout.line_len(stride.nth(first, i), origin) as u128)
+        .sum();
+    lines / period * bytes_per_period(layout, first, origin, stride) as u128 + rest
+}
+
+/// Where the engine sends its buffers.
+///
+/// The engine patches its buffers in place once they've been sent. An output that still reads
+/// from them after `send_slot` returns, like a `vmsplice(2)` pipe does, has a ring of them, and
+/// only hands one back once it's done with it.
+pub trait Output {
+    /// Buffers in the ring.
+    fn ring_len(&self) -> usize;
+    /// Bytes each buffer of the ring holds.
+    fn capacity(&self) -> usize;
+    /// Buffer `slot` of the ring, as soon as it's free to change.
+    fn slot(&mut self, slot: usize) -> io::Result<&mut Buffer>;
+    fn send_slot(&mut self, slot: usize) -> io::Result<()>;
+    /// Sends bytes that are free to change as soon as this returns.
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()>;
+    /// Waits until everything that's been sent is out.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// A ring of one, for anything that copies what it's given.
+pub(crate) struct Copying<'a, W> {
+    out: &'a mut W,
+    buf: Buffer,
+}
+
+impl<'a, W> Copying<'a, W> {
+    pub(crate) fn new(out: &'a mut W, capacity: usize) -> Copying<'a, W> {
+        Copying {
+            out,
+            buf: Buffer::with_capacity(capacity),
+        }
+    }
+}
+
+impl<W: Write> Output for Copying<'_, W> {
+    fn ring_len(&self) -> usize {
+        1
+    }
+    fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+    fn slot(&mut self, _: usize) -> io::Result<&mut Buffer> {
+        Ok(&mut self.buf)
+    }
+    fn send_slot(&mut self, _: usize) -> io::Result<()> {
+        self.out.write_all(self.buf.view())
+    }
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)
+    }
+    /// Flushing `out` is up to the caller, as with any other writer.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Moves every counter on to the next line.
+pub(crate) fn advance(counters: &mut [AsciiCounter], rises: &[bool], step: u64) {
+    for (counter, &rises) in counters.iter_mut().zip(rises) {
+        match rises {
+            true => counter.add(step),
+            false => counter.sub(step),
+        }
+    }
+}
+
+/// Writes `lines` lines from the one for `first`, one at a time, through the counters.
+///
+/// Line numbers count from `origin`.
+pub fn slow_buzz<O: Output>(
+    layout: &Layout,
+    first: u64,
+    lines: u128,
+    origin: i128,
+    stride: Stride,
+    out: &mut O,
+) -> io::Result<()> {
+    let max_line_len = layout.max_line_len();
+    let mut buf = Buffer::new();
+    let mut sites = vec![Vec::new(); layout.fields().len()];
+    let mut counters = layout.counters(first, origin);
+    let rises = layout.rises(stride.descending);
+    for i in 0..lines {
+        if i > 0 {
+            advance(&mut counters, &rises, stride.step);
+        }
+        if buf.spare_capacity() < max_line_len {
+            out.send(buf.view())?;
+            buf.clear();
+        }
+        let n = stride.nth(first, i as u64);
+        layout.render(Class::of(n), &counters, &mut buf, &mut sites)?;
+        sites.iter_mut().for_each(Vec::clear);
+    }
+    out.send(buf.view())
+}
+
+/// Fills a buffer of `capacity` bytes once, and then only patches the numbers, one width segment
+/// at a time.
+pub fn fast_buzz<W: Write>(
+    layout: &Layout,
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    capacity: usize,
+    out: &mut W,
+) -> io::Result<()> {
+    fast_buzz_to(layout, range, stride, &mut Copying::new(out, capacity))
+}
+
+/// Like [`fast_buzz`], but with a ring of buffers to fill and patch in turn.
+///
+/// Negative numbers are written with [`Layout::negated`], counting their magnitude the other way.
+/// Line numbers count from the first line of the sequence, whether or not it gets written: the
+/// start of `range`, or the end when counting down.
+pub fn fast_buzz_to<O: Output>(
+    layout: &Layout,
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    out: &mut O,
+) -> io::Result<()> {
+    for_each_segment(
+        layout,
+        range,
+        stride,
+        |layout, first, lines, origin, stride| {
+            fast_segment(layout, first, lines, origin, stride, out)
+        },
+    )?;
+    out.flush()
+}
+
+/// Bytes [`fast_buzz`] writes for `range`, worked out without generating any of them.
+pub fn buzz_len(layout: &Layout, range: RangeInclusive<i128>, stride: Stride) -> io::Result<u128> {
+    let mut len = 0;
+    for_each_segment(
+        layout,
+        range,
+        stride,
+        |layout, first, lines, origin, stride| {
+            len += segment_bytes(layout, first, lines, origin, stride);
+            Ok(())
+        },
+    )?;
+    Ok(len)
+}
+
+/// Splits the lines of `range` into runs where every field keeps its width, and hands each one to
+/// `segment` as `(layout, first, lines, origin, stride)`, in order.
+///
+/// Negative numbers get [`Layout::negated`], counting their magnitude the other way. Padded
+/// numbers can't be negative, as the sign would make them a byte wider than the rest.
+pub(crate) fn for_each_segment(
+    layout: &Layout,
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    mut segment: impl FnMut(&Layout, u64, u128, i128, Stride) -> io::Result<()>,
+) -> io::Result<()> {
+    let (low, high) = range.into_inner();
+    let padded = |field: &Field| field.value == Value::N && field.fmt.pad != Pad::None;
+    if low < 0 && layout.fields().iter().any(padded) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "padded numbers can't be negative",
+        ));
+    }
+    let (step, offset) = (stride.step as i128, stride.offset as i128);
+    let (origin, first, room) = match stride.descending {
+        true => (high, high - offset, high - offset - low),
+        false => (low, low + offset, high - low - offset),
+    };
+    if room < 0 {
+        return Ok(());
+    }
+    let lines = (room / step + 1) as u128;
+
+    let negated = layout.negated();
+    let mut run = |first: i128, lines: u128| match u64::try_from(first) {
+        Ok(first) => for_each_run_segment(layout, first, lines, origin, stride, &mut segment),
+        Err(_) => {
+            let stride = Stride {
+                descending: !stride.descending,
+                ..stride
+            };
+            for_each_run_segment(
+                &negated,
+                first.unsigned_abs() as u64,
+                lines,
+                -origin,
+                stride,
+                &mut segment,
+            )
+        }
+    };
+    // Lines until we cross zero
+    let before = match (stride.descending, first < 0) {
+        (false, true) => (-first - 1) / step + 1,
+        (true, false) => first / step + 1,
+        _ => lines as i128,
+    };
+    let before = std::cmp::min(lines, before as u128);
+    run(first, before)?;
+    if before < lines {
+        let next = match stride.descending {
+            true => first - before as i128 * step,
+            false => first + before as i128 * step,
+        };
+        run(next, lines - before)?;
+    }
+    Ok(())
+}
+
+/// The segments of `lines` lines from the one for `first`, all on the same side of zero.
+fn for_each_run_segment(
+    layout: &Layout,
+    mut first: u64,
+    mut lines: u128,
+    origin: i128,
+    stride: Stride,
+    segment: &mut impl FnMut(&Layout, u64, u128, i128, Stride) -> io::Result<()>,
+) -> io::Result<()> {
+    loop {
+        let span = layout.segment_len(first, origin, stride.descending);
+        let segment_lines = std::cmp::min(lines, (span / stride.step) as u128 + 1);
+        segment(layout, first, segment_lines, origin, stride)?;
+        lines -= segment_lines;
+        if lines == 0 {
+            return Ok(());
+        }
+        first = stride.nth(first, segment_lines as u64);
+    }
+}
+
+/// Every field has to render to the same width for all `lines` lines from `first`.
+pub(crate) fn fast_segment<O: Output>(
+    layout: &Layout,
+    first: u64,
+    lines: u128,
+    origin: i128,
+    stride: Stride,
+    out: &mut O,
+) -> io::Result<()> {
+    let ring = out.ring_len();
+    let bytes_per_period = bytes_per_period(layout, first, origin, stride);
+    // `--only` and the stride can leave every line of the period empty
+    if bytes_per_period == 0 {
+        return Ok(());
+    }
+    let Some(batch) = find_batch(bytes_per_period, out.capacity(), stride, ring) else {
+        return slow_buzz(layout, first, lines, origin, stride, out);
+    };
+    let full_batches = lines / batch.lines as u128;
+    if full_batches == 0 {
+        return slow_buzz(layout, first, lines, origin, stride, out);
+    }
+
+    // 1: fill and send, a batch per buffer
+    let mut sites = vec![Vec::new(); layout.fields().len()];
+    let mut spare_sites = sites.clone();
+    let mut counters = layout.counters(first, origin);
+    let rises = layout.rises(stride.descending);
+    let filled = std::cmp::min(full_batches, ring as u128) as u64;
+    for slot in 0..filled {
+        let buf = out.slot(slot as usize)?;
+        buf.clear();
+        let sites = match slot {
+            0 => &mut sites,
+            _ => &mut spare_sites,
+        };
+        for i in slot * batch.lines..(slot + 1) * batch.lines {
+            if i > 0 {
+                advance(&mut counters, &rises, stride.step);
+            }
+            layout.render(Class::of(stride.nth(first, i)), &counters, buf, sites)?;
+        }
+        spare_sites.iter_mut().for_each(Vec::clear);
+        out.send_slot(slot as usize)?;
+    }
+
+    // 2: patch and resend, each buffer moving `ring` batches on
+    for b in ring as u128..full_batches {
+        let slot = (b % ring as u128) as usize;
+        let buf = out.slot(slot)?;
+        // SAFETY: the segment's numbers all have the same width
+        unsafe { patch(buf.view_mut(), layout, &sites, &rises, &batch) };
+        out.send_slot(slot)?;
+    }
+
+    let done = full_batches * batch.lines as u128;
+    if done < lines {
+        let rest = stride.nth(first, done as u64);
+        slow_buzz(layout, rest, lines - done, origin, stride, out)?;
+    }
+    Ok(())
+}
+
+/// Moves every number in `bytes` on by a batch, given the `sites` of their ones digits.
+///
+/// # Safety
+///
+/// Every number has to keep its width, as it does within a segment, so no carry or borrow runs
+/// off its front.
+pub(crate) unsafe fn patch(
+    bytes: &mut [u8],
+    layout: &Layout,
+    sites: &[Vec<usize>],
+    rises: &[bool],
+    batch: &Batch,
+) {
+    // SAFETY, for every ripple: a number that keeps its width has a digit for it to stop at
+    for ((field, sites), &rises) in layout.fields().iter().zip(sites).zip(rises) {
+        for &(exp, digit) in &batch.digits {
+            let shift = field.fmt.byte_offset(exp);
+            match (rises, field.fmt.sep) {
+                (true, None) => {
+                    for &site in sites {
+                        unsafe { ripple_carry_add_ascii(bytes, site - shift, digit) };
+                    }
+                }
+                (true, Some(sep)) => {
+                    for &site in sites {
+                        unsafe { ripple_carry_add_ascii_grouped(bytes, site - shift, digit, sep) };
+                    }
+                }
+                (false, None) => {
+                    for &site in sites {
+                        unsafe { ripple_borrow_sub_ascii(bytes, site - shift, digit) };
+                    }
+                }
+                (false, Some(sep)) => {
+                    for &site in sites {
+                        unsafe { ripple_borrow_sub_ascii_grouped(bytes, site - shift, digit, sep) };
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::tests::spelled;
+    use crate::counter::{NumFormat, Pad};
+    use crate::mmap::mmap_buzz;
+    use crate::parallel::parallel_buzz;
+
+    /// Every line the stride lands on is left out, so every period is empty.
+    fn empty_periods() -> [(Layout, Stride); 3] {
+        let fizz = Layout::plain(NumFormat::default()).only(&[Class::Fizz]);
+        let fizz_buzz = Layout::plain(NumFormat::default()).only(&[Class::FizzBuzz]);
+        let every = |step, descending| Stride {
+            step,
+            offset: 0,
+            descending,
+        };
+        [
+            (fizz.clone(), every(15, false)),
+            (fizz_buzz, every(3, false)),
+            (fizz, every(15, true)),
+        ]
+    }
+
+    #[test]
+    fn empty_periods_write_nothing() {
+        for (layout, stride) in empty_periods() {
+            let mut out = Vec::new();
+            fast_buzz(&layout, -1000..=100_000, stride, 4096, &mut out).unwrap();
+            assert!(out.is_empty());
+            assert_eq!(buzz_len(&layout, -1000..=100_000, stride).unwrap(), 0);
+        }
+    }
+
+    /// Formats every line of `range` with `line`, given the number and the line number.
+    fn reference(
+        range: RangeInclusive<i128>,
+        stride: Stride,
+        line: impl Fn(i128, u64) -> String,
+    ) -> Vec<u8> {
+        let (low, high) = range.into_inner();
+        let (origin, dir) = match stride.descending {
+            true => (high, -1),
+            false => (low, 1),
+        };
+        let mut out = String::new();
+        let mut n = origin + dir * stride.offset as i128;
+        while low <= n && n <= high {
+            out += &line(n, (n - origin).unsigned_abs() as u64 + 1);
+            n += dir * stride.step as i128;
+        }
+        out.into_bytes()
+    }
+
+    /// Checks the engine against [`reference`], and [`buzz_len`] with it.
+    fn check(
+        layout: &Layout,
+        range: RangeInclusive<i128>,
+        stride: Stride,
+        line: impl Fn(i128, u64) -> String,
+    ) {
+        let expected = reference(range.clone(), stride, line);
+        let mut out = Vec::new();
+        fast_buzz(layout, range.clone(), stride, 4096, &mut out).unwrap();
+        assert!(out == expected, "{range:?} {stride:?}");
+        assert_eq!(
+            buzz_len(layout, range, stride).unwrap(),
+            expected.len() as u128
+        );
+    }
+
+    /// Across 9 to 10, 999 to 1000 and 999,999 to 1,000,000, and up to `u64::MAX`.
+    const RANGES: [RangeInclusive<i128>; 3] = [
+        1..=20_000,
+        990_000..=1_010_000,
+        u64::MAX as i128 - 20_000..=u64::MAX as i128,
+    ];
+
+    fn word(class: Class) -> &'static str {
+        match class {
+            Class::Number => unreachable!(),
+            Class::Fizz => "Fizz",
+            Class::Buzz => "Buzz",
+            Class::FizzBuzz => "FizzBuzz",
+        }
+    }
+
+    /// What a line of [`Layout::plain`] holds, without the newline.
+    fn plain(n: i128, fmt: NumFormat) -> String {
+        let magnitude = n.unsigned_abs() as u64;
+        match Class::of(magnitude) {
+            Class::Number if n < 0 => format!("-{}", spelled(magnitude, fmt)),
+            Class::Number => spelled(magnitude, fmt),
+            class => match fmt.pad {
+                Pad::None => word(class).to_string(),
+                Pad::Zeros | Pad::Spaces => format!("{:>1$}", word(class), fmt.len(1)),
+            },
+        }
+    }
+
+    #[test]
+    fn plain_matches_format() {
+        let fmt = |pad, width| NumFormat {
+            pad,
+            width,
+            sep: None,
+        };
+        let fmts = [
+            NumFormat::default(),
+            fmt(Pad::Zeros, 6),
+            fmt(Pad::Spaces, 3),
+            NumFormat::padded(Pad::Zeros),
+            NumFormat::padded(Pad::Spaces),
+        ];
+        for fmt in fmts {
+            let layout = Layout::plain(fmt);
+            for range in RANGES {
+                check(&layout, range, Stride::ALL, |n, _| {
+                    format!("{}\n", plain(n, fmt))
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn grouped_matches_format() {
+        let fmt = |pad, width, sep| NumFormat {
+            pad,
+            width,
+            sep: Some(sep),
+        };
+        // Carries hop the separators as batches are patched
+        let fmts = [
+            fmt(Pad::None, 0, b','),
+            fmt(Pad::Zeros, 7, b'_'),
+            fmt(Pad::Spaces, 9, b'.'),
+        ];
+        for fmt in fmts {
+            let layout = Layout::plain(fmt);
+            for range in RANGES {
+                check(&layout, range, Stride::ALL, |n, _| {
+                    format!("{}\n", plain(n, fmt))
+                });
+            }
+        }
+    }
+
+    /// The number, or the word for it.
+    fn value(n: i128) -> Result<i128, &'static str> {
+        match Class::of(n.unsigned_abs() as u64) {
+            Class::Number => Ok(n),
+            class => Err(word(class)),
+        }
+    }
+
+    fn jsonl(n: i128) -> String {
+        match value(n) {
+            Ok(n) => format!("{{\"n\":{n},\"v\":{n}}}\n"),
+            Err(w) => format!("{{\"n\":{n},\"v\":\"{w}\"}}\n"),
+        }
+    }
+
+    #[test]
+    fn jsonl_matches_format() {
+        // Two numbers to patch on every number line
+        for range in RANGES {
+            check(&Layout::jsonl(), range, Stride::ALL, |n, _| jsonl(n));
+        }
+    }
+
+    fn delimited(n: i128, sep: char) -> String {
+        match value(n) {
+            Ok(n) => format!("{n}{sep}{n}\n"),
+            Err(w) => format!("{n}{sep}{w}\n"),
+        }
+    }
+
+    #[test]
+    fn delimited_matches_format() {
+        for sep in [',', '\t'] {
+            let layout = Layout::delimited(sep as u8);
+            for range in RANGES {
+                check(&layout, range, Stride::ALL, |n, _| delimited(n, sep));
+            }
+        }
+    }
+
+    #[test]
+    fn numbered_matches_format() {
+        let fmt = NumFormat::default();
+        let layouts: [(Layout, &dyn Fn(i128) -> String); 3] = [
+            (Layout::plain(fmt), &|n| format!("{}\n", plain(n, fmt))),
+            (Layout::jsonl(), &jsonl),
+            (Layout::delimited(b','), &|n| delimited(n, ',')),
+        ];
+        for (layout, line) in layouts {
+            let layout = layout.numbered();
+            for range in RANGES {
+                check(&layout, range, Stride::ALL, |n, line_no| {
+                    format!("{line_no:>6}\t{}", line(n))
+                });
+            }
+        }
+        // Line numbers past the padding
+        let layout = Layout::plain(fmt).numbered();
+        check(&layout, 1..=1_000_100, Stride::ALL, |n, line_no| {
+            format!("{line_no:>6}\t{}\n", plain(n, fmt))
+        });
+    }
+
+    #[test]
+    fn only_matches_format() {
+        let selections: [&[Class]; 4] = [
+            &[Class::Number],
+            &[Class::Fizz, Class::Buzz],
+            &[Class::FizzBuzz],
+            &[Class::Number, Class::FizzBuzz],
+        ];
+        for only in selections {
+            // Lines keep their numbers from the full sequence
+            let layout = Layout::delimited(b',').numbered().only(only);
+            for range in RANGES {
+                check(&layout, range, Stride::ALL, |n, line_no| {
+                    match only.contains(&Class::of(n.unsigned_abs() as u64)) {
+                        true => format!("{line_no:>6}\t{}", delimited(n, ',')),
+                        false => String::new(),
+                    }
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn strides_match_format() {
+        let fmt = NumFormat {
+            pad: Pad::None,
+            width: 0,
+            sep: Some(b','),
+        };
+        let layout = Layout::plain(fmt).numbered();
+        // Periods of 15 / gcd(15, step) lines: 15, 5, 3, 15 and 1
+        for (step, offset) in [(2, 0), (3, 1), (5, 4), (7, 3), (45, 10)] {
+            for descending in [false, true] {
+                let stride = Stride {
+                    step,
+                    offset,
+                    descending,
+                };
+                for range in RANGES {
+                    check(&layout, range, stride, |n, line_no| {
+                        format!("{line_no:>6}\t{}\n", plain(n, fmt))
+                    });
+                }
+            }
+        }
+        // Only Fizz lines, every 7th line from the 3rd
+        let layout = Layout::plain(fmt).numbered().only(&[Class::Fizz]);
+        let stride = Stride {
+            step: 7,
+            offset: 2,
+            descending: false,
+        };
+        check(&layout, 1..=100_000, stride, |n, line_no| {
+            match Class::of(n as u64) {
+                Class::Fizz => format!("{line_no:>6}\tFizz\n"),
+                _ => String::new(),
+            }
+        });
+    }
+
+    #[test]
+    fn negatives_match_format() {
+        let grouped = NumFormat {
+            pad: Pad::None,
+            width: 0,
+            sep: Some(b','),
+        };
+        let layouts: [(Layout, &dyn Fn(i128) -> String); 3] = [
+            (Layout::plain(grouped), &|n| {
+                format!("{}\n", plain(n, grouped))
+            }),
+            (Layout::jsonl(), &jsonl),
+            (Layout::delimited(b'\t'), &|n| delimited(n, '\t')),
+        ];
+        let ranges = [-20_000..=20_000, -1_010_000..=-990_000];
+        for (layout, line) in layouts {
+            let layout = layout.numbered();
+            for range in ranges.clone() {
+                // Across zero both ways
+                for descending in [false, true] {
+                    let stride = Stride {
+                        descending,
+                        ..Stride::ALL
+                    };
+                    check(&layout, range.clone(), stride, |n, line_no| {
+                        format!("{line_no:>6}\t{}", line(n))
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn padded_negatives_are_refused() {
+        for pad in [Pad::Zeros, Pad::Spaces] {
+            let layout = Layout::plain(NumFormat::padded(pad));
+            let e = fast_buzz(&layout, -3..=1, Stride::ALL, 4096, &mut Vec::new()).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(buzz_len(&layout, -3..=1, Stride::ALL).is_err());
+        }
+    }
+
+    #[test]
+    fn countdown_is_the_sequence_backwards() {
+        let fmt = |pad, width, sep| NumFormat { pad, width, sep };
+        let fmts = [
+            NumFormat::default(),
+            fmt(Pad::None, 0, Some(b',')),
+            fmt(Pad::Zeros, 7, Some(b',')),
+        ];
+        let down = Stride {
+            step: 1,
+            offset: 0,
+            descending: true,
+        };
+        for fmt in fmts {
+            let layout = Layout::plain(fmt);
+            // Down past 1,000,000 and 1000, patching batches across them
+            for range in [900_000..=1_100_000, 1..=20_000] {
+                let mut up = Vec::new();
+                fast_buzz(&layout, range.clone(), Stride::ALL, 4096, &mut up).unwrap();
+                let mut lines: Vec<&[u8]> = up.split_inclusive(|&b| b == b'\n').collect();
+                lines.reverse();
+                let mut counted_down = Vec::new();
+                fast_buzz(&layout, range, down, 4096, &mut counted_down).unwrap();
+                assert!(counted_down == lines.concat());
+            }
+        }
+    }
+
+    fn read_write(path: &std::path::Path) -> std::fs::File {
+        std::fs::File::options()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_periods_write_no_chunks() {
+        let path = std::env::temp_dir().join(format!("firehose-test-{}", std::process::id()));
+        let file = read_write(&path);
+        let mut cases: Vec<_> = empty_periods()
+            .into_iter()
+            .map(|(layout, stride)| (layout, 1..=100_000, stride))
+            .collect();
+        // A segment shorter than a period, with every line of it left out
+        let fizz = Layout::plain(NumFormat::default()).only(&[Class::Fizz]);
+        cases.push((fizz, 1..=2, Stride::ALL));
+        for (layout, range, stride) in cases {
+            parallel_buzz(&layout, range.clone(), stride, 4096, 2, &file, 0).unwrap();
+            assert_eq!(file.metadata().unwrap().len(), 0);
+            mmap_buzz(&layout, range, stride, 4096, 2, &file, 0).unwrap();
+            assert_eq!(file.metadata().unwrap().len(), 0);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn chunks_add_up_to_the_stream() {
+        let path = std::env::temp_dir().join(format!("firehose-chunks-{}", std::process::id()));
+        let file = read_write(&path);
+        let down = Stride {
+            step: 3,
+            offset: 1,
+            descending: true,
+        };
+        // Every width from 1 to 7 digits, in chunks of 64 KiB
+        let cases = [
+            (
+                Layout::plain(NumFormat::default()),
+                1..=2_000_000,
+                Stride::ALL,
+            ),
+            (Layout::jsonl().numbered(), -1_500_000..=200_000, down),
+        ];
+        for (layout, range, stride) in cases {
+            let mut expected = Vec::new();
+            fast_buzz(&layout, range.clone(), stride, 4096, &mut expected).unwrap();
+            for threads in [1, 4, 7] {
+                file.set_len(0).unwrap();
+                parallel_buzz(&layout, range.clone(), stride, 4096, threads, &file, 0).unwrap();
+                assert!(std::fs::read(&path).unwrap() == expected);
+                file.set_len(0).unwrap();
+                mmap_buzz(&layout, range.clone(), stride, 4096, threads, &file, 0).unwrap();
+                assert!(std::fs::read(&path).unwrap() == expected);
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn empty_segments_map_nothing() {
+        let path = std::env::temp_dir().join(format!("firehose-mmap-{}", std::process::id()));
+        let file = read_write(&path);
+        let fmt = NumFormat {
+            pad: Pad::Zeros,
+            width: 0,
+            sep: Some(b'_'),
+        };
+        let layout = Layout::plain(fmt).numbered().only(&[Class::FizzBuzz]);
+        let stride = Stride {
+            step: 7,
+            offset: 0,
+            descending: false,
+        };
+        let mut expected = Vec::new();
+        fast_buzz(&layout, -2612..=2388, stride, 4096, &mut expected).unwrap();
+        mmap_buzz(&layout, -2612..=2388, stride, 4096, 2, &file, 0).unwrap();
+        assert!(std::fs::read(&path).unwrap() == expected);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,387 @@
+//! Output through io_uring, with raw syscalls.
+//!
+//! The buffers are registered with the ring up front and written with `IORING_OP_WRITE_FIXED`,
+//! so the kernel doesn't have to pin their pages for every write. Writes to the same file can run
+//! in any order, so a batch of them goes in as one linked chain, and the next chain only goes in
+//! once that one's done. While one chain is being written, the engine patches the buffers for the
+//! next one.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::buffer::Buffer;
+use crate::sys::{self, Iovec, Mapping};
+use crate::template::Output;
+
+/// Writes per chain.
+const CHAIN_LEN: usize = 4;
+/// One chain being written, and one being patched.
+const RING_LEN: usize = 2 * CHAIN_LEN;
+
+const IORING_OFF_SQ_RING: i64 = 0;
+const IORING_OFF_CQ_RING: i64 = 0x800_0000;
+const IORING_OFF_SQES: i64 = 0x1000_0000;
+const IORING_OP_WRITE_FIXED: u8 = 5;
+const IORING_OP_WRITE: u8 = 23;
+const IOSQE_IO_LINK: u8 = 1 << 2;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_REGISTER_BUFFERS: u32 = 0;
+const IORING_REGISTER_PROBE: u32 = 8;
+const IO_URING_OP_SUPPORTED: u16 = 1;
+const ECANCELED: i32 = 125;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct ProbeOp {
+    op: u8,
+    resv: u8,
+    flags: u16,
+    resv2: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Probe {
+    last_op: u8,
+    ops_len: u8,
+    resv: u16,
+    resv2: [u32; 3],
+    ops: [ProbeOp; IORING_OP_WRITE as usize + 1],
+}
+
+#[repr(C)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+pub struct Uring {
+    ring_fd: OwnedFd,
+    out: RawFd,
+    params: Params,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    /// Whether the buffers got registered. Without, we fall back to plain `IORING_OP_WRITE`.
+    fixed: bool,
+    ring: Vec<Buffer>,
+    /// Slots sent, but not submitted yet.
+    pending: Vec<usize>,
+    /// Slots in the chain that's being written, in order.
+    in_flight: Vec<usize>,
+    results: [i32; RING_LEN],
+}
+
+impl Uring {
+    /// Fails if the kernel doesn't do io_uring, won't let us use it, or can't do the writes we'd
+    /// put in.
+    pub fn new(out: RawFd, capacity: usize) -> io::Result<Uring> {
+        let mut params = Params::default();
+        let ring_fd = unsafe { sys::io_uring_setup(CHAIN_LEN as u32, &mut params) }?;
+        let fd = ring_fd.as_raw_fd();
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let sq = Mapping::new(fd, sq_len, IORING_OFF_SQ_RING)?;
+        let cq = Mapping::new(fd, cq_len, IORING_OFF_CQ_RING)?;
+        let sqes_len = params.sq_entries as usize * size_of::<Sqe>();
+        let sqes = Mapping::new(fd, sqes_len, IORING_OFF_SQES)?;
+
+        let ring: Vec<Buffer> = (0..RING_LEN)
+            .map(|_| Buffer::with_capacity(capacity))
+            .collect();
+        let iovecs: Vec<Iovec> = ring
+            .iter()
+            .map(|buf| Iovec {
+                base: buf.as_ptr(),
+                len: buf.capacity(),
+            })
+            .collect();
+        // Usually down to RLIMIT_MEMLOCK
+        let fixed = unsafe {
+            sys::io_uring_register(
+                fd,
+                IORING_REGISTER_BUFFERS,
+                iovecs.as_ptr(),
+                RING_LEN as u32,
+            )
+        }
+        .is_ok();
+
+        // 5.1 to 5.5 set up a ring fine, but have no IORING_OP_WRITE and no writing at the file's
+        // own position. Neither do they have the probe, so a failed probe says as much.
+        let mut probe = Probe::default();
+        let ops = probe.ops.len() as u32;
+        // The kernel fills it in
+        let arg = (&raw mut probe).cast_const();
+        unsafe { sys::io_uring_register(fd, IORING_REGISTER_PROBE, arg, ops) }?;
+        let opcode = match fixed {
+            true => IORING_OP_WRITE_FIXED,
+            false => IORING_OP_WRITE,
+        };
+        let op = probe.ops[opcode as usize];
+        if opcode > probe.last_op || op.flags & IO_URING_OP_SUPPORTED == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("io_uring can't do opcode {opcode}"),
+            ));
+        }
+
+        Ok(Uring {
+            ring_fd,
+            out,
+            params,
+            sq,
+            cq,
+            sqes,
+            fixed,
+            ring,
+            pending: Vec::with_capacity(CHAIN_LEN),
+            in_flight: Vec::with_capacity(CHAIN_LEN),
+            results: [0; RING_LEN],
+        })
+    }
+
+    fn sq_u32(&self, offset: u32) -> *mut u32 {
+        unsafe { self.sq.ptr().add(offset as usize) as *mut u32 }
+    }
+    fn cq_u32(&self, offset: u32) -> *mut u32 {
+        unsafe { self.cq.ptr().add(offset as usize) as *mut u32 }
+    }
+    fn atomic(ptr: *mut u32) -> &'static AtomicU32 {
+        unsafe { AtomicU32::from_ptr(ptr) }
+    }
+
+    /// Puts the pending slots in as one chain, once the one before it is done.
+    fn submit(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.wait()?;
+
+        let off = &self.params.sq_off;
+        let mask = unsafe { *self.sq_u32(off.ring_mask) };
+        let array = self.sq_u32(off.array);
+        let tail = Self::atomic(self.sq_u32(off.tail));
+        let mut t = tail.load(Ordering::Relaxed);
+        for (i, &slot) in self.pending.iter().enumerate() {
+            let buf = &self.ring[slot];
+            let index = t & mask;
+            let sqe = Sqe {
+                opcode: match self.fixed {
+                    true => IORING_OP_WRITE_FIXED,
+                    false => IORING_OP_WRITE,
+                },
+                flags: match i + 1 == self.pending.len() {
+                    true => 0,
+                    false => IOSQE_IO_LINK,
+                },
+                ioprio: 0,
+                fd: self.out,
+                // The file's own position, like write(2)
+                off: u64::MAX,
+                addr: buf.as_ptr() as u64,
+                len: buf.len() as u32,
+                rw_flags: 0,
+                user_data: slot as u64,
+                buf_index: slot as u16,
+                personality: 0,
+                splice_fd_in: 0,
+                addr3: 0,
+                pad: 0,
+            };
+            unsafe {
+                (self.sqes.ptr() as *mut Sqe).add(index as usize).write(sqe);
+                array.add(index as usize).write(index);
+            }
+            t = t.wrapping_add(1);
+        }
+        tail.store(t, Ordering::Release);
+
+        let n = self.pending.len() as u32;
+        let mut submitted = 0;
+        while submitted < n {
+            submitted += sys::io_uring_enter(self.ring_fd.as_raw_fd(), n - submitted, 0, 0)?;
+        }
+        std::mem::swap(&mut self.pending, &mut self.in_flight);
+        Ok(())
+    }
+
+    /// Waits for the chain in flight, and finishes off any writes in it that came up short.
+    fn wait(&mut self) -> io::Result<()> {
+        let mut left = self.in_flight.len();
+        let off = &self.params.cq_off;
+        let mask = unsafe { *self.cq_u32(off.ring_mask) };
+        let cqes = unsafe { self.cq.ptr().add(off.cqes as usize) as *const Cqe };
+        let head = Self::atomic(self.cq_u32(off.head));
+        let tail = Self::atomic(self.cq_u32(off.tail));
+        while left > 0 {
+            let mut h = head.load(Ordering::Relaxed);
+            let t = tail.load(Ordering::Acquire);
+            if h == t {
+                sys::io_uring_enter(
+                    self.ring_fd.as_raw_fd(),
+                    0,
+                    left as u32,
+                    IORING_ENTER_GETEVENTS,
+                )?;
+                continue;
+            }
+            while h != t {
+                let cqe = unsafe { &*cqes.add((h & mask) as usize) };
+                self.results[cqe.user_data as usize] = cqe.res;
+                h = h.wrapping_add(1);
+                left -= 1;
+            }
+            head.store(h, Ordering::Release);
+        }
+
+        // A short write cancels the rest of the chain, so those go out the slow way
+        for slot in self.in_flight.drain(..) {
+            let bytes = self.ring[slot].view();
+            let written = match self.results[slot] {
+                res if res >= 0 => res as usize,
+                res if res == -ECANCELED => 0,
+                res => return Err(io::Error::from_raw_os_error(-res)),
+            };
+            if written < bytes.len() {
+                let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(self.out) });
+                file.write_all(&bytes[written..])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Output for Uring {
+    fn ring_len(&self) -> usize {
+        RING_LEN
+    }
+    fn capacity(&self) -> usize {
+        self.ring[0].capacity()
+    }
+    fn slot(&mut self, slot: usize) -> io::Result<&mut Buffer> {
+        // Segments start over at slot 0, so a slot can come back before its chain went in
+        if self.pending.contains(&slot) {
+            self.submit()?;
+        }
+        if self.in_flight.contains(&slot) {
+            self.wait()?;
+        }
+        Ok(&mut self.ring[slot])
+    }
+    fn send_slot(&mut self, slot: usize) -> io::Result<()> {
+        self.pending.push(slot);
+        if self.pending.len() == CHAIN_LEN {
+            self.submit()?;
+        }
+        Ok(())
+    }
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.flush()?;
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(self.out) });
+        file.write_all(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.submit()?;
+        self.wait()
+    }
+}
+
+impl Drop for Uring {
+    /// The kernel mustn't be left writing from buffers we're about to free.
+    fn drop(&mut self) {
+        let _ = self.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::NumFormat;
+    use crate::layout::Layout;
+    use crate::template::{Stride, fast_buzz, fast_buzz_to};
+
+    #[test]
+    fn segments_ending_on_a_batch_wait_for_their_chain() {
+        let path = std::env::temp_dir().join(format!("firehose-uring-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let layout = Layout::plain(NumFormat::default());
+        // Ends 1-999 on a whole batch, and 1000-9999 starts over at slot 0
+        let range = 1..=100_000;
+        let Ok(mut uring) = Uring::new(file.as_raw_fd(), 16 * 1024) else {
+            std::fs::remove_file(path).unwrap();
+            return;
+        };
+        fast_buzz_to(&layout, range.clone(), Stride::ALL, &mut uring).unwrap();
+        drop(uring);
+        let mut expected = Vec::new();
+        fast_buzz(&layout, range, Stride::ALL, 16 * 1024, &mut expected).unwrap();
+        assert!(std::fs::read(&path).unwrap() == expected);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+//! Zero-copy output into a pipe.
+//!
+//! `write(2)` copies every buffer into the pipe. `vmsplice(2)` maps the buffer's pages into the
+//! pipe instead, and the reader copies straight out of them. The catch is that the pages are read
+//! whenever the reader gets round to it, so a buffer can't be patched for its next batch until
+//! everything spliced from it has been read. With a ring of buffers that's comfortably bigger than
+//! the pipe, that's always the case by the time we come back to a buffer.
+//!
+//! The pages might still be referenced after that if the reader moves them on with `splice(2)`
+//! rather than reading them, so this is only for readers that `read(2)`.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, RawFd};
+
+use crate::buffer::Buffer;
+use crate::sys;
+use crate::template::Output;
+
+pub struct Vmsplice {
+    fd: RawFd,
+    pipe_size: usize,
+    ring: Vec<Buffer>,
+    /// Bytes sent so far.
+    sent: u64,
+    /// `sent` when each buffer was last spliced.
+    marks: Vec<u64>,
+}
+
+impl Vmsplice {
+    /// Fails if `fd` isn't a pipe.
+    pub fn new(fd: RawFd, capacity: usize) -> io::Result<Vmsplice> {
+        let pipe_size = sys::pipe_size(fd)?;
+        // Buffers are usually at least half full, so this is at least two pipes' worth
+        let ring_len = 2 * pipe_size.div_ceil(capacity) + 1;
+        Ok(Vmsplice {
+            fd,
+            pipe_size,
+            ring: (0..ring_len)
+                .map(|_| Buffer::with_capacity(capacity))
+                .collect(),
+            sent: 0,
+            marks: vec![0; ring_len],
+        })
+    }
+}
+
+impl Output for Vmsplice {
+    fn ring_len(&self) -> usize {
+        self.ring.len()
+    }
+    fn capacity(&self) -> usize {
+        self.ring[0].capacity()
+    }
+    fn slot(&mut self, slot: usize) -> io::Result<&mut Buffer> {
+        // Free once everything that's still in the pipe went in after it
+        let since = (self.sent - self.marks[slot]) as usize;
+        while since < self.pipe_size && since < sys::pipe_len(self.fd)? {
+            std::thread::yield_now();
+        }
+        Ok(&mut self.ring[slot])
+    }
+    fn send_slot(&mut self, slot: usize) -> io::Result<()> {
+        let mut bytes = self.ring[slot].view();
+        while !bytes.is_empty() {
+            let n = unsafe { sys::splice_to_pipe(self.fd, bytes) }?;
+            bytes = &bytes[n..];
+        }
+        self.sent += self.ring[slot].len() as u64;
+        self.marks[slot] = self.sent;
+        Ok(())
+    }
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // Not through `Stdout`, whose buffering would put these out of order with the splices
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(self.fd) });
+        file.write_all(bytes)?;
+        self.sent += bytes.len() as u64;
+        Ok(())
+    }
+    /// Everything's in the pipe as soon as it's sent.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::buffer::Buffer;
+use crate::layout::Class;
+
+const ONES: [&str; 20] = [
+    "", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven",
+    "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const SCALES: [&str; GROUPS] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+/// `u64::MAX` is a little over 18 quintillion.
+const GROUPS: usize = 7;
+/// Comfortably more than the longest spelling, `seven hundred seventy-seven quintillion ...`
+const MAX_LINE_LEN: usize = 512;
+
+/// `0..1000` spelled out, `""` for zero.
+fn spell_group(n: u16) -> String {
+    let (hundreds, rest) = (n / 100, (n % 100) as usize);
+    let mut s = String::new();
+    if hundreds > 0 {
+        s.push_str(ONES[hundreds as usize]);
+        s.push_str(" hundred");
+    }
+    if rest > 0 {
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        if rest < 20 {
+            s.push_str(ONES[rest]);
+        } else {
+            s.push_str(TENS[rest / 10]);
+            if rest % 10 > 0 {
+                s.push('-');
+                s.push_str(ONES[rest % 10]);
+            }
+        }
+    }
+    s
+}
+
+/// Like `AsciiCounter`, but in words.
+///
+/// Only the lowest group of three digits changes from line to line, so that's looked up in a
+/// table, and everything above it is cached until the next carry.
+pub struct WordCounter {
+    table: Vec<Vec<u8>>,
+    groups: [u16; GROUPS],
+    prefix: Vec<u8>,
+}
+
+impl WordCounter {
+    pub fn new(start: u64) -> WordCounter {
+        let mut groups = [0; GROUPS];
+        let mut n = start;
+        for group in groups.iter_mut() {
+            *group = (n % 1000) as u16;
+            n /= 1000;
+        }
+        let mut counter = WordCounter {
+            table: (0..1000).map(|n| spell_group(n).into_bytes()).collect(),
+            groups,
+            prefix: Vec::new(),
+        };
+        counter.update_prefix();
+        counter
+    }
+    fn update_prefix(&mut self) {
+        self.prefix.clear();
+        for (group, scale) in self.groups.iter().zip(SCALES).skip(1).rev() {
+            if *group > 0 {
+                if !self.prefix.is_empty() {
+                    self.prefix.push(b' ');
+                }
+                self.prefix.extend_from_slice(&self.table[*group as usize]);
+                self.prefix.push(b' ');
+                self.prefix.extend_from_slice(scale.as_bytes());
+            }
+        }
+    }
+    pub fn bump(&mut self) {
+        self.groups[0] += 1;
+        if self.groups[0] == 1000 {
+            self.groups[0] = 0;
+            for group in self.groups.iter_mut().skip(1) {
+                if *group == 999 {
+                    *group = 0;
+                } else {
+                    *group += 1;
+                    break;
+                }
+            }
+            self.update_prefix();
+        }
+    }
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let low = &self.table[self.groups[0] as usize];
+        match (self.prefix.is_empty(), low.is_empty()) {
+            (true, true) => out.write_all(b"zero"),
+            (true, false) => out.write_all(low),
+            (false, true) => out.write_all(&self.prefix),
+            (false, false) => {
+                out.write_all(&self.prefix)?;
+                out.write_all(b" ")?;
+                out.write_all(low)
+            }
+        }
+    }
+}
+
+/// FizzBuzz, but the numbers are spelled out: `one`, `two`, `Fizz`, `four`, `Buzz`, ...
+pub fn word_buzz<W: Write>(range: RangeInclusive<u64>, out: &mut W) -> io::Result<()> {
+    if range.is_empty() {
+        return Ok(());
+    }
+    let (mut n, end) = range.into_inner();
+    let mut buf = Buffer::new();
+    let mut counter = WordCounter::new(n);
+    loop {
+        if buf.spare_capacity() < MAX_LINE_LEN {
+            out.write_all(buf.view())?;
+            buf.clear();
+        }
+        match Class::of(n) {
+            Class::FizzBuzz => buf.write_all(b"FizzBuzz\n")?,
+            Class::Fizz => buf.write_all(b"Fizz\n")?,
+            Class::Buzz => buf.write_all(b"Buzz\n")?,
+            Class::Number => {
+                counter.write_to(&mut buf)?;
+                buf.write_all(b"\n")?;
+            }
+        }
+        if n == end {
+            break;
+        }
+        n += 1;
+        counter.bump();
+    }
+    out.write_all(buf.view())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The plain recursive way.
+    fn spoken(n: u64) -> String {
+        match n {
+            0 => "zero".to_string(),
+            _ => spoken_nonzero(n),
+        }
+    }
+
+    fn spoken_nonzero(n: u64) -> String {
+        let join = |head: String, rest: u64, sep: &str| match rest {
+            0 => head,
+            _ => format!("{head}{sep}{}", spoken_nonzero(rest)),
+        };
+        match n {
+            0..20 => ONES[n as usize].to_string(),
+            20..100 => join(TENS[n as usize / 10].to_string(), n % 10, "-"),
+            100..1000 => join(format!("{} hundred", ONES[n as usize / 100]), n % 100, " "),
+            _ => {
+                let scale = n.ilog(1000);
+                let unit = 1000_u64.pow(scale);
+                let head = format!("{} {}", spoken_nonzero(n / unit), SCALES[scale as usize]);
+                join(head, n % unit, " ")
+            }
+        }
+    }
+
+    #[test]
+    fn groups_match_the_recursive_spelling() {
+        assert_eq!(spell_group(0), "");
+        for n in 1..1000 {
+            assert_eq!(spell_group(n), spoken(n as u64));
+        }
+    }
+
+    #[test]
+    fn bumps_match_the_recursive_spelling() {
+        // Across 999 to 1000 and 999,999 to 1,000,000, a carry through every group, and the top
+        let starts = [0, 990, 999_990, 999_999_999_999_990, u64::MAX - 20];
+        for start in starts {
+            let mut counter = WordCounter::new(start);
+            for n in start..=start.saturating_add(20) {
+                let mut line = Vec::new();
+                counter.write_to(&mut line).unwrap();
+                assert_eq!(String::from_utf8(line).unwrap(), spoken(n));
+                if n < u64::MAX {
+                    counter.bump();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn word_buzz_matches_the_recursive_spelling() {
+        for range in [1..=2_000, u64::MAX - 100..=u64::MAX] {
+            let mut out = Vec::new();
+            word_buzz(range.clone(), &mut out).unwrap();
+            let expected: String = range
+                .map(|n| match Class::of(n) {
+                    Class::Number => format!("{}\n", spoken(n)),
+                    Class::Fizz => "Fizz\n".to_string(),
+                    Class::Buzz => "Buzz\n".to_string(),
+                    Class::FizzBuzz => "FizzBuzz\n".to_string(),
+                })
+                .collect();
+            assert!(out == expected.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Gathered output with `writev(2)`.
+//!
+//! The template engine keeps whole batches of lines in a buffer, so every `Fizz\n` and
+//! `Buzz\nFizz\n` still goes through a copy into the buffer once, and out of it on every write.
+//! Here only the lines with numbers in them get a buffer, to be patched like the engine's. The rest
+//! come out the same every period, so they're rendered once, and every write points back at them.
+//!
+//! It doesn't pay: `bench` has it at about a tenth of the template engine. The slices are only a
+//! line or two long, and the kernel spends more on each one than the copy it saves.
+
+use std::fs::File;
+use std::io::{self, IoSlice, Write};
+use std::ops::{Range, RangeInclusive};
+
+use crate::buffer::Buffer;
+use crate::layout::{Class, Layout, Piece};
+use crate::template::{
+    Copying, Stride, advance, fast_segment, find_batch, for_each_segment, patch, slow_buzz,
+};
+
+/// Where a run of lines is kept.
+#[derive(Clone, PartialEq, Eq)]
+enum Fragment {
+    Numbers(Range<usize>),
+    Static(Range<usize>),
+}
+
+impl Fragment {
+    /// Takes in `next` if it starts where this one ends.
+    fn join(&mut self, next: &Fragment) -> bool {
+        match (self, next) {
+            (Fragment::Numbers(a), Fragment::Numbers(b))
+            | (Fragment::Static(a), Fragment::Static(b))
+                if a.end == b.start =>
+            {
+                a.end = b.end;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Like [`fast_buzz`](crate::template::fast_buzz), but gathering every batch from the number
+/// lines and the static ones with a single `writev(2)`. Only the number lines count towards
+/// `capacity`.
+pub fn gather_buzz(
+    layout: &Layout,
+    range: RangeInclusive<i128>,
+    stride: Stride,
+    capacity: usize,
+    out: &mut File,
+) -> io::Result<()> {
+    for_each_segment(
+        layout,
+        range,
+        stride,
+        |layout, first, lines, origin, stride| {
+            gather_segment(layout, first, lines, origin, stride, capacity, out)
+        },
+    )
+}
+
+/// Every field has to render to the same width for all `lines` lines from `first`.
+fn gather_segment(
+    layout: &Layout,
+    first: u64,
+    lines: u128,
+    origin: i128,
+    stride: Stride,
+    capacity: usize,
+    out: &mut File,
+) -> io::Result<()> {
+    let period = stride.period();
+    let has_numbers = |class: Class| {
+        layout
+            .line(class)
+            .iter()
+            .any(|piece| matches!(piece, Piece::Num(_)))
+    };
+    let number_bytes: usize = (0..period)
+        .map(|i| stride.nth(first, i))
+        .filter(|&n| has_numbers(Class::of(n)))
+        .map(|n| layout.line_len(n, origin))
+        .sum();
+    // Nothing to write at all
+    if (0..period).all(|i| layout.line_len(stride.nth(first, i), origin) == 0) {
+        return Ok(());
+    }
+    // Nothing to gather, it's all static
+    if number_bytes == 0 {
+        return fast_segment(
+            layout,
+            first,
+            lines,
+            origin,
+            stride,
+            &mut Copying::new(out, capacity),
+        );
+    }
+    let batch = find_batch(number_bytes, capacity, stride, 1);
+    let Some(batch) = batch.filter(|batch| lines >= batch.lines as u128) else {
+        return slow_buzz(
+            layout,
+            first,
+            lines,
+            origin,
+            stride,
+            &mut Copying::new(out, capacity),
+        );
+    };
+    let full_batches = lines / batch.lines as u128;
+
+    // 1: fill the number lines, and the static ones for a period
+    let mut numbers = Buffer::with_capacity(capacity);
+    let mut statics = Buffer::new();
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut static_lines = vec![0..0; period as usize];
+    let mut sites = vec![Vec::new(); layout.fields().len()];
+    let mut counters = layout.counters(first, origin);
+    let rises = layout.rises(stride.descending);
+    for i in 0..batch.lines {
+        if i > 0 {
+            advance(&mut counters, &rises, stride.step);
+        }
+        let class = Class::of(stride.nth(first, i));
+        let fragment = match has_numbers(class) {
+            true => {
+                let start = numbers.len();
+                layout.render(class, &counters, &mut numbers, &mut sites)?;
+                Fragment::Numbers(start..numbers.len())
+            }
+            false if i < period => {
+                let start = statics.len();
+                layout.render(class, &counters, &mut statics, &mut sites)?;
+                static_lines[i as usize] = start..statics.len();
+                Fragment::Static(start..statics.len())
+            }
+            false => Fragment::Static(static_lines[(i % period) as usize].clone()),
+        };
+        if !fragments
+            .last_mut()
+            .is_some_and(|last| last.join(&fragment))
+        {
+            fragments.push(fragment);
+        }
+    }
+
+    // 2: write, patch, and write again
+    for b in 0..full_batches {
+        if b > 0 {
+            // SAFETY: the segment's numbers all have the same width
+            unsafe { patch(numbers.view_mut(), layout, &sites, &rises, &batch) };
+        }
+        let mut slices: Vec<IoSlice> = fragments
+            .iter()
+            .map(|fragment| match fragment {
+                Fragment::Numbers(range) => IoSlice::new(&numbers.view()[range.clone()]),
+                Fragment::Static(range) => IoSlice::new(&statics.view()[range.clone()]),
+            })
+            .collect();
+        write_all_vectored(out, &mut slices)?;
+    }
+
+    let done = full_batches * batch.lines as u128;
+    if done < lines {
+        let rest = stride.nth(first, done as u64);
+        let mut out = Copying::new(out, capacity);
+        slow_buzz(layout, rest, lines - done, origin, stride, &mut out)?;
+    }
+    Ok(())
+}
+
+/// `writev(2)` until it's all out. std caps each call at `IOV_MAX` slices.
+fn write_all_vectored(out: &mut File, mut slices: &mut [IoSlice]) -> io::Result<()> {
+    while !slices.is_empty() {
+        match out.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}